@@ -0,0 +1,148 @@
+use crate::file_system::FileSystem;
+use crate::os_strings::{name_bytes, with_suffix};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackupControl {
+    /// How backups are named, same values as GNU 'mv --backup' takes
+    None,
+    Simple,
+    Numbered,
+    Existing,
+}
+
+impl FromStr for BackupControl {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" | "off" => Ok(BackupControl::None),
+            "simple" | "never" => Ok(BackupControl::Simple),
+            "numbered" | "t" => Ok(BackupControl::Numbered),
+            "existing" | "nil" => Ok(BackupControl::Existing),
+            _ => Err(format!(
+                "wrong backup control '{}', expected one of: none, simple, numbered, existing",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupStyle {
+    /// 'name' + suffix (e.g. 'name~' or 'name.bak'), previous backup is replaced
+    Simple(String),
+    /// 'name.~1~', 'name.~2~' and so on, previous backups are kept
+    Numbered,
+    /// Numbered if the file already has numbered backups, simple with the suffix otherwise
+    Existing(String),
+}
+
+impl Default for BackupStyle {
+    fn default() -> Self {
+        BackupStyle::Simple("~".to_string())
+    }
+}
+
+impl BackupStyle {
+    /// Makes backup style from control value and suffix for simple backups, None means no backups
+    pub fn new(backup_control: BackupControl, suffix: &str) -> Option<Self> {
+        match backup_control {
+            BackupControl::None => None,
+            BackupControl::Simple => Some(BackupStyle::Simple(suffix.to_string())),
+            BackupControl::Numbered => Some(BackupStyle::Numbered),
+            BackupControl::Existing => Some(BackupStyle::Existing(suffix.to_string())),
+        }
+    }
+
+    /// Name to keep the existing target_filename under before it is overwritten
+    pub fn backup_name(&self, file_system: &dyn FileSystem, target_filename: &Path) -> PathBuf {
+        let numbered = |number: usize| with_suffix(target_filename, &format!(".~{}~", number));
+        match self {
+            BackupStyle::Simple(suffix) => with_suffix(target_filename, suffix),
+            BackupStyle::Numbered => numbered(last_backup_number(file_system, target_filename) + 1),
+            BackupStyle::Existing(suffix) => match last_backup_number(file_system, target_filename)
+            {
+                0 => with_suffix(target_filename, suffix),
+                number => numbered(number + 1),
+            },
+        }
+    }
+}
+
+/// Finds the biggest N among the numbered backups 'name.~N~' of the file, 0 if there are none
+fn last_backup_number(file_system: &dyn FileSystem, target_filename: &Path) -> usize {
+    let Some(name) = target_filename.file_name() else {
+        return 0;
+    };
+    let directory = match target_filename.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let Ok(names) = file_system.read_dir(directory) else {
+        return 0;
+    };
+    let prefix = [&*name_bytes(name), b".~"].concat();
+    names
+        .iter()
+        .filter_map(|entry_name| {
+            name_bytes(entry_name)
+                .strip_prefix(prefix.as_slice())
+                .and_then(|rest| rest.strip_suffix(b"~"))
+                .and_then(|number| std::str::from_utf8(number).ok()?.parse::<usize>().ok())
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backups::{BackupControl, BackupStyle};
+    use crate::file_system::StdFileSystem;
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_backup_names() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().to_str().unwrap().to_string();
+        let target = root.clone() + "/report.txt";
+        let target_path = Path::new(&target);
+        let expected = |suffix: &str| PathBuf::from(target.clone() + suffix);
+        assert_eq!("t".parse(), Ok(BackupControl::Numbered));
+        assert_eq!(BackupStyle::new(BackupControl::None, "~"), None);
+        let simple = BackupStyle::new(BackupControl::Simple, ".bak").unwrap();
+        let numbered = BackupStyle::new(BackupControl::Numbered, ".bak").unwrap();
+        let existing = BackupStyle::new(BackupControl::Existing, ".bak").unwrap();
+        assert_eq!(
+            simple.backup_name(&StdFileSystem, target_path),
+            expected(".bak")
+        );
+        assert_eq!(
+            numbered.backup_name(&StdFileSystem, target_path),
+            expected(".~1~")
+        );
+        assert_eq!(
+            existing.backup_name(&StdFileSystem, target_path),
+            expected(".bak")
+        );
+        let _ = File::create(target.clone() + ".~1~");
+        let _ = File::create(target.clone() + ".~7~");
+        let _ = File::create(target.clone() + ".~x~");
+        assert_eq!(
+            numbered.backup_name(&StdFileSystem, target_path),
+            expected(".~8~")
+        );
+        assert_eq!(
+            existing.backup_name(&StdFileSystem, target_path),
+            expected(".~8~")
+        );
+        assert_eq!(
+            BackupStyle::default().backup_name(&StdFileSystem, Path::new("report.txt")),
+            PathBuf::from("report.txt~")
+        );
+    }
+}
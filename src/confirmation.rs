@@ -0,0 +1,305 @@
+use crate::file_system::StdFileSystem;
+use crate::files_operations::occupied_targets;
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq)]
+pub struct ConfirmedMoves {
+    /// Pairs the user agreed to move. overwrite tells for every pair whether its target exists
+    /// and the user agreed to replace it
+    pub initial_filenames: Vec<PathBuf>,
+    pub target_filenames: Vec<PathBuf>,
+    pub overwrite: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Answer {
+    Yes,
+    No,
+    All,
+    Quit,
+}
+
+impl Answer {
+    fn name(&self) -> &'static str {
+        match self {
+            Answer::Yes => "yes",
+            Answer::No => "no",
+            Answer::All => "all",
+            Answer::Quit => "quit",
+        }
+    }
+}
+
+/// Asks question until one of the allowed answers (full or first letter) is given.
+/// End of input means 'quit'
+fn ask<R: BufRead, W: Write>(
+    question: &str,
+    allowed_answers: &[Answer],
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<Answer> {
+    let names: Vec<&str> = allowed_answers.iter().map(Answer::name).collect();
+    loop {
+        write!(output, "{} [{}] ", question, names.join("/"))?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(Answer::Quit);
+        }
+        let reply = line.trim().to_ascii_lowercase();
+        let answer = allowed_answers.iter().find(|answer| {
+            !reply.is_empty() && (reply == answer.name() || reply == answer.name()[..1])
+        });
+        if let Some(answer) = answer {
+            return Ok(*answer);
+        }
+    }
+}
+
+/// Asks the user about every pair before moving
+///
+/// Answers: 'yes' moves the file, 'no' leaves it, 'all' moves it and all the next files
+/// without questions, 'quit' leaves it and all the next files. If ask_overwrite is set,
+/// there is a separate question about every agreed pair which target exists, whether
+/// to overwrite it. Declining it leaves the pair too
+pub fn confirm_moves<R: BufRead, W: Write>(
+    initial_filenames: &[PathBuf],
+    target_filenames: &[PathBuf],
+    ask_overwrite: bool,
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<ConfirmedMoves> {
+    let mut confirmed = ConfirmedMoves {
+        initial_filenames: vec![],
+        target_filenames: vec![],
+        overwrite: vec![],
+    };
+    let mut accept_all = false;
+    for i in 0..initial_filenames.len() {
+        if !accept_all {
+            let question = format!(
+                "mmv: move '{}' to '{}'?",
+                initial_filenames[i].display(),
+                target_filenames[i].display()
+            );
+            match ask(
+                &question,
+                &[Answer::Yes, Answer::No, Answer::All, Answer::Quit],
+                input,
+                output,
+            )? {
+                Answer::Yes => {}
+                Answer::No => continue,
+                Answer::All => accept_all = true,
+                Answer::Quit => break,
+            }
+        }
+        confirmed
+            .initial_filenames
+            .push(initial_filenames[i].clone());
+        confirmed.target_filenames.push(target_filenames[i].clone());
+        confirmed.overwrite.push(false);
+    }
+    if !ask_overwrite {
+        return Ok(confirmed);
+    }
+    // Files of the declined pairs stay where they are, so the targets are checked for the
+    // agreed pairs only, and again after every pair that is left
+    let mut quit = false;
+    loop {
+        let occupied = occupied_targets(
+            &StdFileSystem,
+            &confirmed.initial_filenames,
+            &confirmed.target_filenames,
+        );
+        let Some(i) = (0..occupied.len()).find(|&i| occupied[i] && !confirmed.overwrite[i]) else {
+            return Ok(confirmed);
+        };
+        let answer = match quit {
+            true => Answer::No,
+            false => {
+                let question = format!(
+                    "mmv: overwrite existing '{}'?",
+                    confirmed.target_filenames[i].display()
+                );
+                ask(
+                    &question,
+                    &[Answer::Yes, Answer::No, Answer::Quit],
+                    input,
+                    output,
+                )?
+            }
+        };
+        let left_pairs = match answer {
+            Answer::Yes => {
+                confirmed.overwrite[i] = true;
+                continue;
+            }
+            Answer::Quit => {
+                quit = true;
+                i..confirmed.initial_filenames.len()
+            }
+            _ => i..i + 1,
+        };
+        confirmed.initial_filenames.drain(left_pairs.clone());
+        confirmed.target_filenames.drain(left_pairs.clone());
+        confirmed.overwrite.drain(left_pairs);
+    }
+}
+
+/// Asks about every pair on the controlling terminal, so it works even if
+/// the standard input is taken by the files list
+pub fn confirm_moves_on_terminal(
+    initial_filenames: &[PathBuf],
+    target_filenames: &[PathBuf],
+    ask_overwrite: bool,
+) -> io::Result<ConfirmedMoves> {
+    let terminal = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    let mut output = terminal.try_clone()?;
+    confirm_moves(
+        initial_filenames,
+        target_filenames,
+        ask_overwrite,
+        &mut BufReader::new(terminal),
+        &mut output,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::confirmation::{confirm_moves, ConfirmedMoves};
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_confirm_moves() {
+        let initial_filenames: Vec<PathBuf> = vec!["a".into(), "b".into(), "c".into(), "d".into()];
+        let target_filenames: Vec<PathBuf> = vec![
+            "new_a".into(),
+            "new_b".into(),
+            "new_c".into(),
+            "new_d".into(),
+        ];
+        let mut output: Vec<u8> = vec![];
+        let confirmed = confirm_moves(
+            &initial_filenames,
+            &target_filenames,
+            true,
+            &mut "y\nmaybe\nn\nall\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            confirmed,
+            ConfirmedMoves {
+                initial_filenames: vec!["a".into(), "c".into(), "d".into()],
+                target_filenames: vec!["new_a".into(), "new_c".into(), "new_d".into()],
+                overwrite: vec![false; 3]
+            }
+        );
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .starts_with("mmv: move 'a' to 'new_a'? [yes/no/all/quit] "));
+
+        let confirmed = confirm_moves(
+            &initial_filenames,
+            &target_filenames,
+            true,
+            &mut "n\nq\n".as_bytes(),
+            &mut vec![],
+        )
+        .unwrap();
+        assert!(confirmed.initial_filenames.is_empty());
+        let confirmed = confirm_moves(
+            &initial_filenames,
+            &target_filenames,
+            true,
+            &mut "y\n".as_bytes(),
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(confirmed.initial_filenames, vec![PathBuf::from("a")]);
+    }
+
+    #[test]
+    fn test_confirm_overwrite() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().to_str().unwrap().to_string();
+        let paths = |names: [&str; 2]| -> Vec<PathBuf> {
+            names
+                .iter()
+                .map(|name| PathBuf::from(root.clone() + name))
+                .collect()
+        };
+        let initial_filenames = paths(["/a", "/b"]);
+        let target_filenames = paths(["/old_a", "/old_b"]);
+        let _ = File::create(&target_filenames[0]);
+        let _ = File::create(&target_filenames[1]);
+        let mut output: Vec<u8> = vec![];
+        let confirmed = confirm_moves(
+            &initial_filenames,
+            &target_filenames,
+            true,
+            &mut "all\nno\nyes\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            confirmed,
+            ConfirmedMoves {
+                initial_filenames: vec![PathBuf::from(root.clone() + "/b")],
+                target_filenames: vec![PathBuf::from(root.clone() + "/old_b")],
+                overwrite: vec![true]
+            }
+        );
+        assert!(String::from_utf8(output).unwrap().ends_with(
+            &("mmv: overwrite existing '".to_string() + &root + "/old_b'? [yes/no/quit] ")
+        ));
+        let confirmed = confirm_moves(
+            &initial_filenames,
+            &target_filenames,
+            false,
+            &mut "y\ny\n".as_bytes(),
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(confirmed.initial_filenames, initial_filenames);
+    }
+
+    #[test]
+    fn test_confirm_declined_chain() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = |name: &str| directory.path().join(name);
+        File::create(path("a")).unwrap();
+        File::create(path("b")).unwrap();
+        // 'b' is not moved away, so 'a -> b' is going to overwrite it
+        let initial_filenames = vec![path("a"), path("b")];
+        let target_filenames = vec![path("b"), path("c")];
+        let mut output: Vec<u8> = vec![];
+        let confirmed = confirm_moves(
+            &initial_filenames,
+            &target_filenames,
+            true,
+            &mut "yes\nno\nno\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        assert!(confirmed.initial_filenames.is_empty());
+        assert!(String::from_utf8(output).unwrap().ends_with(&format!(
+            "mmv: overwrite existing '{}'? [yes/no/quit] ",
+            path("b").display()
+        )));
+        let confirmed = confirm_moves(
+            &initial_filenames,
+            &target_filenames,
+            true,
+            &mut "yes\nyes\n".as_bytes(),
+            &mut vec![],
+        )
+        .unwrap();
+        assert_eq!(confirmed.overwrite, [false, false]);
+    }
+}
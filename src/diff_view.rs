@@ -0,0 +1,112 @@
+use std::env::var_os;
+use std::io::{stdout, IsTerminal};
+
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PairStyle {
+    /// How a pair of old and new names is shown in the report
+    /// 'old -> new'
+    #[default]
+    Plain,
+    /// Only the changed parts are highlighted: with colors common parts are dimmed, removed text
+    /// is red and inserted text is green; without colors it is 'common{removed -> inserted}common'
+    Diff { colored: bool },
+}
+
+impl PairStyle {
+    /// Diff style is colored only if the standard output is a terminal and NO_COLOR is not set
+    pub fn diff_for_stdout() -> Self {
+        let no_color = var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        PairStyle::Diff {
+            colored: !no_color && stdout().is_terminal(),
+        }
+    }
+
+    /// Shows the pair with the given arrow between the names ('->' or '<->')
+    pub fn show(&self, old_name: &str, new_name: &str, arrow: &str) -> String {
+        let (prefix, old_middle, new_middle, suffix) = split_changes(old_name, new_name);
+        match self {
+            PairStyle::Plain => format!("{} {} {}", old_name, arrow, new_name),
+            PairStyle::Diff { colored: false } => {
+                format!(
+                    "{}{{{} {} {}}}{}",
+                    prefix, old_middle, arrow, new_middle, suffix
+                )
+            }
+            PairStyle::Diff { colored: true } => {
+                let side = |middle: &str, color: &str| {
+                    format!(
+                        "{DIM}{}{RESET}{color}{}{RESET}{DIM}{}{RESET}",
+                        prefix, middle, suffix
+                    )
+                };
+                format!(
+                    "{} {} {}",
+                    side(old_middle, RED),
+                    arrow,
+                    side(new_middle, GREEN)
+                )
+            }
+        }
+    }
+}
+
+/// Splits two names into common prefix, changed middles of both and common suffix
+fn split_changes<'a>(old_name: &'a str, new_name: &'a str) -> (&'a str, &'a str, &'a str, &'a str) {
+    let prefix_length: usize = old_name
+        .chars()
+        .zip(new_name.chars())
+        .take_while(|(old_symbol, new_symbol)| old_symbol == new_symbol)
+        .map(|(symbol, _)| symbol.len_utf8())
+        .sum();
+    // Suffix must not overlap the prefix in either name ('aa' -> 'aaa')
+    let max_suffix_length = old_name.len().min(new_name.len()) - prefix_length;
+    let suffix_length: usize = old_name[prefix_length..]
+        .chars()
+        .rev()
+        .zip(new_name[prefix_length..].chars().rev())
+        .take_while(|(old_symbol, new_symbol)| old_symbol == new_symbol)
+        .map(|(symbol, _)| symbol.len_utf8())
+        .scan(0, |length, symbol_length| {
+            *length += symbol_length;
+            (*length <= max_suffix_length).then_some(symbol_length)
+        })
+        .sum();
+    (
+        &old_name[..prefix_length],
+        &old_name[prefix_length..old_name.len() - suffix_length],
+        &new_name[prefix_length..new_name.len() - suffix_length],
+        &old_name[old_name.len() - suffix_length..],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff_view::PairStyle;
+
+    #[test]
+    fn test_show_pair() {
+        let old_name = "music/rock/A - B.mp3";
+        let new_name = "music/rock/B - A.mp3";
+        assert_eq!(
+            PairStyle::Plain.show(old_name, new_name, "->"),
+            "music/rock/A - B.mp3 -> music/rock/B - A.mp3"
+        );
+        let diff = PairStyle::Diff { colored: false };
+        assert_eq!(
+            diff.show(old_name, new_name, "->"),
+            "music/rock/{A - B -> B - A}.mp3"
+        );
+        assert_eq!(diff.show("aa.txt", "aaa.txt", "->"), "aa{ -> a}.txt");
+        assert_eq!(diff.show("Ж.txt", "Жж.txt", "->"), "Ж{ -> ж}.txt");
+        assert_eq!(
+            PairStyle::Diff { colored: true }.show("a_1.txt", "b_1.txt", "->"),
+            "\x1b[2m\x1b[0m\x1b[31ma\x1b[0m\x1b[2m_1.txt\x1b[0m -> \
+             \x1b[2m\x1b[0m\x1b[32mb\x1b[0m\x1b[2m_1.txt\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use crate::os_strings::{name_bytes, name_from_bytes};
+use std::env::{temp_dir, var};
+use std::fs::{read, remove_file, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{id, Command, Stdio};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum EditError {
+    /// Custom error handling failures of editing the new names by hand
+    #[error("mmv: Not able to prepare file for editing: '{0}'")]
+    FileError(String),
+    #[error("mmv: Editor '{0}' failed, nothing is moved")]
+    EditorError(String),
+    #[error(
+        "mmv: Line {0} of edited file is not '<number><TAB><new name>' with a number from the list"
+    )]
+    FormatError(usize),
+}
+
+/// Builds text of the file to edit: every pair takes a comment line with old name
+/// and a line with its number and new name separated by tab.
+/// The text is bytes, so names that are not valid UTF-8 are written unchanged
+pub fn write_edit_text(initial_filenames: &[PathBuf], target_filenames: &[PathBuf]) -> Vec<u8> {
+    let mut text = b"# mmv: change the new names below, keep the numbers untouched.\n\
+         # Delete a line to leave the file where it is. Lines starting with '#' are ignored.\n"
+        .to_vec();
+    for i in 0..initial_filenames.len() {
+        text.extend_from_slice(b"\n# ");
+        text.extend_from_slice(&name_bytes(initial_filenames[i].as_os_str()));
+        text.extend_from_slice(format!("\n{}\t", i + 1).as_bytes());
+        text.extend_from_slice(&name_bytes(target_filenames[i].as_os_str()));
+        text.push(b'\n');
+    }
+    text
+}
+
+/// Parses edited text back into pairs of old and new names
+///
+/// Pairs with deleted lines are left out, so those files are not moved
+pub fn parse_edit_text(
+    text: &[u8],
+    initial_filenames: &[PathBuf],
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>), EditError> {
+    let mut edited_targets: Vec<Option<PathBuf>> = vec![None; initial_filenames.len()];
+    for (line_index, line) in text.split(|&byte| byte == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.trim_ascii().is_empty() || line.starts_with(b"#") {
+            continue;
+        }
+        let format_error = || EditError::FormatError(line_index + 1);
+        let tab_index = line
+            .iter()
+            .position(|&byte| byte == b'\t')
+            .ok_or_else(format_error)?;
+        let (number, target_filename) = (&line[..tab_index], &line[tab_index + 1..]);
+        let index = std::str::from_utf8(number)
+            .ok()
+            .and_then(|number| number.trim().parse::<usize>().ok())
+            .filter(|number| (1..=initial_filenames.len()).contains(number))
+            .ok_or_else(format_error)?
+            - 1;
+        if target_filename.is_empty() || edited_targets[index].is_some() {
+            return Err(format_error());
+        }
+        edited_targets[index] = Some(PathBuf::from(name_from_bytes(target_filename.to_vec())));
+    }
+    Ok(initial_filenames
+        .iter()
+        .zip(edited_targets)
+        .filter_map(|(initial_filename, target_filename)| {
+            target_filename.map(|target_filename| (initial_filename.clone(), target_filename))
+        })
+        .unzip())
+}
+
+/// Opens the pairs in the user's editor ($VISUAL, $EDITOR or 'vi') and returns the edited pairs
+pub fn edit_files_pairs(
+    initial_filenames: &[PathBuf],
+    target_filenames: &[PathBuf],
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>), EditError> {
+    let edit_file = temp_dir().join(format!("mmv-edit-{}.txt", id()));
+    let edit_file_name = edit_file.to_string_lossy().to_string();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&edit_file)
+        .map_err(|_| EditError::FileError(edit_file_name.clone()))?;
+    let text = write_edit_text(initial_filenames, target_filenames);
+    if file.write_all(&text).is_err() {
+        let _ = remove_file(&edit_file);
+        return Err(EditError::FileError(edit_file_name));
+    }
+    drop(file);
+    let edited_text = run_editor(&edit_file)
+        .and_then(|_| read(&edit_file).map_err(|_| EditError::FileError(edit_file_name.clone())));
+    let _ = remove_file(&edit_file);
+    parse_edit_text(&edited_text?, initial_filenames)
+}
+
+fn run_editor(edit_file: &Path) -> Result<(), EditError> {
+    let editor = var("VISUAL")
+        .or_else(|_| var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // The standard input may be taken by the files list already, so the editor reads the
+    // controlling terminal, as the confirmation does. Without terminal the input is inherited
+    let input = match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        Ok(terminal) => Stdio::from(terminal),
+        Err(_) => Stdio::inherit(),
+    };
+    // Editor may be given with arguments (e.g. 'code --wait'), so it is run by shell
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("mmv")
+        .arg(edit_file)
+        .stdin(input)
+        .status();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        _ => Err(EditError::EditorError(editor)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editing::{parse_edit_text, write_edit_text, EditError};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_edit_text() {
+        let initial_filenames: Vec<PathBuf> = vec!["a.txt".into(), "b.txt".into()];
+        let target_filenames: Vec<PathBuf> = vec!["new_a.txt".into(), "new_b.txt".into()];
+        let text = write_edit_text(&initial_filenames, &target_filenames);
+        assert!(text.ends_with(b"\n# a.txt\n1\tnew_a.txt\n\n# b.txt\n2\tnew_b.txt\n"));
+        assert_eq!(
+            parse_edit_text(&text, &initial_filenames),
+            Ok((initial_filenames.clone(), target_filenames))
+        );
+        assert_eq!(
+            parse_edit_text(b"2\tB v2.txt\r\n# 1\tremoved.txt\n", &initial_filenames),
+            Ok((vec!["b.txt".into()], vec!["B v2.txt".into()]))
+        );
+        assert_eq!(
+            parse_edit_text(b"1\tx.txt\n3\ty.txt\n", &initial_filenames),
+            Err(EditError::FormatError(2))
+        );
+        assert_eq!(
+            parse_edit_text(b"1\tx.txt\n1\ty.txt\n", &initial_filenames),
+            Err(EditError::FormatError(2))
+        );
+        assert_eq!(
+            parse_edit_text(b"1 x.txt\n", &initial_filenames),
+            Err(EditError::FormatError(1))
+        );
+    }
+}
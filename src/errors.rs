@@ -0,0 +1,286 @@
+use crate::editing::EditError;
+use crate::file_system::FileSystem;
+use crate::files_filtering::FilterParseError;
+use crate::files_operations::{ExcludeError, MapError, MoveReport};
+use crate::plans::PlanError;
+use crate::template_handling::TemplateError;
+use glob::PatternError;
+use std::io::{self, ErrorKind};
+use std::mem::discriminant;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MmvError {
+    /// Errors of finding and moving files. Failures of the file system keep the io::Error as source
+    #[error("mmv: Invalid pattern '{pattern}': {source}")]
+    InvalidPattern {
+        pattern: String,
+        #[source]
+        source: PatternError,
+    },
+    #[error("mmv: Files for pattern '{0}' not found")]
+    NoFiles(String),
+    #[error("mmv: Not able to replace existing file: '{}'", .0.display())]
+    TargetExists(PathBuf),
+    #[error("mmv: Several files are going to be moved to the same name: '{}'", .0.display())]
+    Collision(PathBuf),
+    #[error(
+        "mmv: Not able to move '{}' to '{}': permission denied",
+        .initial_filename.display(),
+        .target_filename.display()
+    )]
+    PermissionDenied {
+        initial_filename: PathBuf,
+        target_filename: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(
+        "mmv: Not able to move '{}' to '{}': they are on different filesystems",
+        .initial_filename.display(),
+        .target_filename.display()
+    )]
+    CrossDevice {
+        initial_filename: PathBuf,
+        target_filename: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(
+        "mmv: Not able to move '{}' to '{}': target is a directory",
+        .initial_filename.display(),
+        .target_filename.display()
+    )]
+    TargetIsDirectory {
+        initial_filename: PathBuf,
+        target_filename: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(
+        "mmv: Not able to move '{}': file does not exist anymore",
+        .initial_filename.display()
+    )]
+    SourceVanished {
+        initial_filename: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(
+        "mmv: Not able to move '{}' to '{}': {source}",
+        .initial_filename.display(),
+        .target_filename.display()
+    )]
+    Io {
+        initial_filename: PathBuf,
+        target_filename: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// Cycle of moves or move over a backed up target failed and the file could not be moved
+    /// back from its temporary or backup name
+    #[error(
+        "{source}\nmmv: '{}' is left as '{}'",
+        .initial_filename.display(),
+        .temporary_filename.display()
+    )]
+    Stranded {
+        initial_filename: PathBuf,
+        temporary_filename: PathBuf,
+        #[source]
+        source: Box<MmvError>,
+    },
+    /// Moving stopped on the error, reports are given for the pairs that were done before it
+    #[error("{source}")]
+    Interrupted {
+        reports: Vec<MoveReport>,
+        #[source]
+        source: Box<MmvError>,
+    },
+    #[error("mmv: Not able to read files list from standard input: {0}")]
+    FilesList(#[source] io::Error),
+    #[error("mmv: Not able to ask on the terminal: {0}")]
+    Terminal(#[source] io::Error),
+    /// Errors of templates, exclude patterns, map files, editing, saved plans and filters
+    /// are kept as they are
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error(transparent)]
+    Exclude(#[from] ExcludeError),
+    #[error(transparent)]
+    Map(#[from] MapError),
+    #[error(transparent)]
+    Edit(#[from] EditError),
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+    #[error(transparent)]
+    Filter(#[from] FilterParseError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitCode {
+    /// Exit codes of mmv, so calling scripts can tell what went wrong without parsing the messages.
+    /// Wrong command line arguments are reported by clap with code 2
+    Success = 0,
+    NoMatch = 1,
+    Usage = 2,
+    BadTemplate = 3,
+    Conflict = 4,
+    PartialFailure = 5,
+    IoError = 6,
+    StalePlan = 7,
+}
+
+impl MmvError {
+    /// Class of the failure for the exit code of mmv
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            MmvError::InvalidPattern { .. } => ExitCode::BadTemplate,
+            MmvError::NoFiles(_) => ExitCode::NoMatch,
+            MmvError::TargetExists(_) | MmvError::Collision(_) => ExitCode::Conflict,
+            MmvError::PermissionDenied { .. }
+            | MmvError::CrossDevice { .. }
+            | MmvError::TargetIsDirectory { .. }
+            | MmvError::SourceVanished { .. }
+            | MmvError::Io { .. }
+            | MmvError::FilesList(_)
+            | MmvError::Terminal(_) => ExitCode::IoError,
+            MmvError::Stranded { source, .. } | MmvError::Interrupted { source, .. } => {
+                source.exit_code()
+            }
+            MmvError::Template(_) => ExitCode::BadTemplate,
+            MmvError::Exclude(ExcludeError::InvalidPattern(_)) => ExitCode::BadTemplate,
+            MmvError::Exclude(ExcludeError::ReadError(_)) => ExitCode::IoError,
+            MmvError::Map(MapError::FormatError { .. }) => ExitCode::BadTemplate,
+            MmvError::Map(MapError::ReadError(_)) => ExitCode::IoError,
+            MmvError::Edit(EditError::FormatError(_)) => ExitCode::BadTemplate,
+            MmvError::Edit(_) => ExitCode::IoError,
+            MmvError::Plan(PlanError::FormatError(_)) => ExitCode::BadTemplate,
+            MmvError::Plan(PlanError::ReadError(_) | PlanError::WriteError(_)) => ExitCode::IoError,
+            MmvError::Plan(PlanError::SourceMissing(_) | PlanError::SourceChanged(_)) => {
+                ExitCode::StalePlan
+            }
+            MmvError::Filter(_) => ExitCode::Usage,
+        }
+    }
+
+    /// Tells what exactly went wrong when moving initial_filename to target_filename failed
+    pub fn from_move(
+        file_system: &dyn FileSystem,
+        initial_path: &Path,
+        target_path: &Path,
+        source: io::Error,
+    ) -> Self {
+        let initial_filename = initial_path.to_path_buf();
+        let target_filename = target_path.to_path_buf();
+        match source.kind() {
+            ErrorKind::AlreadyExists => MmvError::TargetExists(target_filename),
+            ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => {
+                MmvError::PermissionDenied {
+                    initial_filename,
+                    target_filename,
+                    source,
+                }
+            }
+            ErrorKind::CrossesDevices => MmvError::CrossDevice {
+                initial_filename,
+                target_filename,
+                source,
+            },
+            ErrorKind::IsADirectory | ErrorKind::DirectoryNotEmpty => MmvError::TargetIsDirectory {
+                initial_filename,
+                target_filename,
+                source,
+            },
+            ErrorKind::NotFound if file_system.symlink_metadata(initial_path).is_err() => {
+                MmvError::SourceVanished {
+                    initial_filename,
+                    source,
+                }
+            }
+            _ => MmvError::Io {
+                initial_filename,
+                target_filename,
+                source,
+            },
+        }
+    }
+}
+
+impl PartialEq for MmvError {
+    /// Sources can not be compared, so errors are equal if they are of the same variant and
+    /// give the same message
+    fn eq(&self, other: &Self) -> bool {
+        discriminant(self) == discriminant(other) && self.to_string() == other.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::{ExitCode, MmvError};
+    use crate::file_system::StdFileSystem;
+    use crate::files_operations::MapError;
+    use crate::plans::PlanError;
+    use crate::template_handling::TemplateError;
+    use std::error::Error;
+    use std::io::{self, ErrorKind};
+    use std::path::Path;
+
+    #[test]
+    fn test_error_from_move() {
+        let error = |kind: ErrorKind| {
+            MmvError::from_move(
+                &StdFileSystem,
+                Path::new("Cargo.toml"),
+                Path::new("new.toml"),
+                io::Error::from(kind),
+            )
+        };
+        assert_eq!(
+            error(ErrorKind::AlreadyExists),
+            MmvError::TargetExists("new.toml".into())
+        );
+        assert!(matches!(
+            error(ErrorKind::CrossesDevices),
+            MmvError::CrossDevice { .. }
+        ));
+        assert!(matches!(error(ErrorKind::NotFound), MmvError::Io { .. }));
+        let vanished = MmvError::from_move(
+            &StdFileSystem,
+            Path::new("missing.toml"),
+            Path::new("new.toml"),
+            io::Error::from(ErrorKind::NotFound),
+        );
+        assert_eq!(
+            vanished.to_string(),
+            "mmv: Not able to move 'missing.toml': file does not exist anymore"
+        );
+        let source = vanished.source().unwrap().downcast_ref::<io::Error>();
+        assert_eq!(source.map(io::Error::kind), Some(ErrorKind::NotFound));
+        assert_eq!(vanished.exit_code(), ExitCode::IoError);
+        assert_eq!(
+            error(ErrorKind::AlreadyExists).exit_code(),
+            ExitCode::Conflict
+        );
+    }
+
+    #[test]
+    fn test_wrapped_errors() {
+        let map_error = MmvError::from(MapError::FormatError {
+            map_file: "renames.csv".to_string(),
+            line: 3,
+        });
+        assert_eq!(
+            map_error.to_string(),
+            "mmv: Line 3 of map file 'renames.csv' is not a pair of old and new names"
+        );
+        assert_eq!(map_error.exit_code(), ExitCode::BadTemplate);
+        let template_error = MmvError::from(TemplateError::UnknownFilter("upper".to_string()));
+        assert_eq!(template_error.exit_code(), ExitCode::BadTemplate);
+        let plan_error = MmvError::from(PlanError::SourceChanged("a.txt".to_string()));
+        assert_eq!(plan_error.exit_code(), ExitCode::StalePlan);
+        let plan_error = MmvError::from(PlanError::ReadError("plan.json".to_string()));
+        assert_eq!(plan_error.exit_code(), ExitCode::IoError);
+    }
+}
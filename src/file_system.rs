@@ -0,0 +1,704 @@
+use crate::normalization::NormalForm;
+use crate::renaming::{exchange_files, rename, rename_no_replace};
+use glob::{MatchOptions, Pattern, PatternError};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileMetadata {
+    /// What mmv needs to know about an existing entry. inode is 0 where there are no inodes
+    pub is_dir: bool,
+    pub is_file: bool,
+    pub is_symlink: bool,
+    pub len: u64,
+    pub modified: SystemTime,
+    pub inode: u64,
+}
+
+/// Operations on files that finding and moving the files are made of
+///
+/// StdFileSystem works with the real files, MemoryFileSystem keeps them in memory
+/// (e.g. for tests, with failures of chosen files)
+pub trait FileSystem: Debug {
+    /// Follows symlinks
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+    /// Does not follow symlinks
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+    /// Names of the entries in the directory
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>>;
+    /// Renames replacing the existing target
+    fn rename(&self, initial_path: &Path, target_path: &Path) -> io::Result<()>;
+    /// Renames only if the target name is free, otherwise fails with ErrorKind::AlreadyExists
+    fn rename_no_replace(&self, initial_path: &Path, target_path: &Path) -> io::Result<()>;
+    /// Exchanges two existing entries
+    fn exchange(&self, first_path: &Path, second_path: &Path) -> io::Result<()>;
+    /// Copies the content of the file replacing the existing target, returns the number of bytes
+    fn copy(&self, initial_path: &Path, target_path: &Path) -> io::Result<u64>;
+    /// Creates the directory with all the missing parents, succeeds if it already exists
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    /// Removes the file, directories are not removed
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Follows symlinks, as Path::exists does
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    /// Finds existing entries by glob pattern, '*' never matches '/'
+    ///
+    /// Directories are read one pattern component at a time and every name is matched in its
+    /// lossy UTF-8 form, so names that are not valid UTF-8 are found too (glob crate skips them)
+    /// and kept exactly as they are. Entries are sorted by name within every directory.
+    /// Component '**' stands for the directory itself and every directory below it ('a/**/*.txt'
+    /// finds 'a/b.txt' and 'a/c/d.txt'), the last component '**' finds the directories below only.
+    /// Symlinks to directories are not entered by '**', and with
+    /// match_options.require_literal_leading_dot hidden directories are not either
+    fn glob(
+        &self,
+        pattern: &str,
+        match_options: MatchOptions,
+    ) -> Result<Vec<PathBuf>, PatternError> {
+        self.glob_normalized(pattern, match_options, None)
+    }
+
+    /// Finds entries the same way as glob, but with normal_form both the pattern and the names
+    /// are compared in it, so NFD names are found by NFC pattern and vice versa.
+    /// Found paths keep the names as they are on the file system
+    fn glob_normalized(
+        &self,
+        pattern: &str,
+        match_options: MatchOptions,
+        normal_form: Option<NormalForm>,
+    ) -> Result<Vec<PathBuf>, PatternError> {
+        Pattern::new(pattern)?;
+        let pattern = match normal_form {
+            Some(normal_form) => normal_form.normalize(pattern),
+            None => pattern.to_string(),
+        };
+        let pattern = pattern.as_str();
+        let (mut paths, relative_pattern) = match pattern.strip_prefix('/') {
+            Some(relative_pattern) => (vec![PathBuf::from("/")], relative_pattern),
+            None => (vec![PathBuf::new()], pattern),
+        };
+        let components: Vec<&str> = relative_pattern
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect();
+        if components.is_empty() {
+            return Ok(vec![]);
+        }
+        for (index, component) in components.iter().enumerate() {
+            let mut found_paths: Vec<PathBuf> = vec![];
+            if *component == "**" {
+                for path in &paths {
+                    if index != components.len() - 1 {
+                        found_paths.push(path.clone());
+                    }
+                    directories_below(self, path, match_options, &mut found_paths);
+                }
+                paths = found_paths;
+                continue;
+            }
+            // ASCII names are the same in every normal form, so only they can be joined as is
+            let is_literal = !component.contains(['*', '?', '['])
+                && (normal_form.is_none() || component.is_ascii());
+            if is_literal {
+                found_paths = paths.iter().map(|path| path.join(component)).collect();
+            } else {
+                let component_pattern = Pattern::new(component)?;
+                for path in &paths {
+                    let directory = match path.as_os_str().is_empty() {
+                        true => Path::new("."),
+                        false => path.as_path(),
+                    };
+                    let Ok(mut names) = self.read_dir(directory) else {
+                        continue;
+                    };
+                    names.sort();
+                    found_paths.extend(
+                        names
+                            .into_iter()
+                            .filter(|name| {
+                                let name = match normal_form {
+                                    Some(normal_form) => normal_form.normalize_name(name),
+                                    None => name.clone(),
+                                };
+                                component_pattern
+                                    .matches_with(&name.to_string_lossy(), match_options)
+                            })
+                            .map(|name| path.join(name)),
+                    );
+                }
+            }
+            paths = match index == components.len() - 1 {
+                true => found_paths
+                    .into_iter()
+                    .filter(|path| self.symlink_metadata(path).is_ok())
+                    .collect(),
+                false => found_paths
+                    .into_iter()
+                    .filter(|path| self.metadata(path).is_ok_and(|metadata| metadata.is_dir))
+                    .collect(),
+            };
+        }
+        Ok(paths)
+    }
+}
+
+/// Adds all the directories below path to found_paths, each one followed by those inside it
+fn directories_below<F: FileSystem + ?Sized>(
+    file_system: &F,
+    path: &Path,
+    match_options: MatchOptions,
+    found_paths: &mut Vec<PathBuf>,
+) {
+    let directory = match path.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => path,
+    };
+    let Ok(mut names) = file_system.read_dir(directory) else {
+        return;
+    };
+    names.sort();
+    for name in names {
+        let hidden = name.to_string_lossy().starts_with('.');
+        if hidden && match_options.require_literal_leading_dot {
+            continue;
+        }
+        let child = path.join(name);
+        if file_system
+            .symlink_metadata(&child)
+            .is_ok_and(|metadata| metadata.is_dir)
+        {
+            found_paths.push(child.clone());
+            directories_below(file_system, &child, match_options, found_paths);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StdFileSystem;
+
+fn file_metadata(metadata: fs::Metadata) -> io::Result<FileMetadata> {
+    Ok(FileMetadata {
+        is_dir: metadata.is_dir(),
+        is_file: metadata.is_file(),
+        is_symlink: metadata.file_type().is_symlink(),
+        len: metadata.len(),
+        modified: metadata.modified()?,
+        inode: inode(&metadata),
+    })
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+impl FileSystem for StdFileSystem {
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        file_metadata(fs::metadata(path)?)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        file_metadata(fs::symlink_metadata(path)?)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        Ok(fs::read_dir(path)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name())
+            .collect())
+    }
+
+    fn rename(&self, initial_path: &Path, target_path: &Path) -> io::Result<()> {
+        rename(initial_path, target_path)
+    }
+
+    fn rename_no_replace(&self, initial_path: &Path, target_path: &Path) -> io::Result<()> {
+        rename_no_replace(initial_path, target_path)
+    }
+
+    fn exchange(&self, first_path: &Path, second_path: &Path) -> io::Result<()> {
+        exchange_files(first_path, second_path)
+    }
+
+    fn copy(&self, initial_path: &Path, target_path: &Path) -> io::Result<u64> {
+        fs::copy(initial_path, target_path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum MemoryEntry {
+    File {
+        content: Vec<u8>,
+        modified: SystemTime,
+    },
+    Directory,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    /// Entries by their paths, exactly as they were given (no symlinks, '.' and '..' are not resolved).
+    /// Every operation that touches a path of failures fails with the given kind of error
+    entries: Mutex<BTreeMap<PathBuf, MemoryEntry>>,
+    failures: Mutex<HashMap<PathBuf, ErrorKind>>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        MemoryFileSystem::default()
+    }
+
+    /// Adds file with the parent directories, modification time is UNIX_EPOCH
+    pub fn add_file(&self, path: impl AsRef<Path>, content: &str) {
+        let path = path.as_ref();
+        let mut entries = self.entries.lock().unwrap();
+        add_parents(&mut entries, path);
+        entries.insert(
+            path.to_path_buf(),
+            MemoryEntry::File {
+                content: content.as_bytes().to_vec(),
+                modified: UNIX_EPOCH,
+            },
+        );
+    }
+
+    pub fn set_modified(&self, path: impl AsRef<Path>, time: SystemTime) {
+        if let Some(MemoryEntry::File { modified, .. }) =
+            self.entries.lock().unwrap().get_mut(path.as_ref())
+        {
+            *modified = time;
+        }
+    }
+
+    /// Content of the file, None if it is missing or it is a directory
+    pub fn read_file(&self, path: impl AsRef<Path>) -> Option<String> {
+        match self.entries.lock().unwrap().get(path.as_ref()) {
+            Some(MemoryEntry::File { content, .. }) => {
+                Some(String::from_utf8_lossy(content).to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Paths of all the files (directories are left out) in sorted order
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| matches!(entry, MemoryEntry::File { .. }))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Makes every following operation with the path fail with the given kind of error
+    pub fn fail_on(&self, path: impl AsRef<Path>, kind: ErrorKind) {
+        self.failures
+            .lock()
+            .unwrap()
+            .insert(path.as_ref().to_path_buf(), kind);
+    }
+
+    fn check_failures(&self, paths: &[&Path]) -> io::Result<()> {
+        let failures = self.failures.lock().unwrap();
+        match paths.iter().find_map(|path| failures.get(*path)) {
+            Some(kind) => Err(io::Error::from(*kind)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn add_parents(entries: &mut BTreeMap<PathBuf, MemoryEntry>, path: &Path) {
+    for parent in path.ancestors().skip(1) {
+        if !parent.as_os_str().is_empty() {
+            entries
+                .entry(parent.to_path_buf())
+                .or_insert(MemoryEntry::Directory);
+        }
+    }
+}
+
+fn has_parent(entries: &BTreeMap<PathBuf, MemoryEntry>, path: &Path) -> bool {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            entries.get(parent) == Some(&MemoryEntry::Directory)
+        }
+        _ => true,
+    }
+}
+
+/// Takes the entry with everything inside it, paths are made relative to it
+fn take_subtree(
+    entries: &mut BTreeMap<PathBuf, MemoryEntry>,
+    path: &Path,
+) -> Vec<(PathBuf, MemoryEntry)> {
+    let paths: Vec<PathBuf> = entries
+        .keys()
+        .filter(|entry_path| entry_path.starts_with(path))
+        .cloned()
+        .collect();
+    paths
+        .into_iter()
+        .map(|entry_path| {
+            let entry = entries.remove(&entry_path).unwrap();
+            (entry_path.strip_prefix(path).unwrap().to_path_buf(), entry)
+        })
+        .collect()
+}
+
+fn put_subtree(
+    entries: &mut BTreeMap<PathBuf, MemoryEntry>,
+    path: &Path,
+    subtree: Vec<(PathBuf, MemoryEntry)>,
+) {
+    for (relative_path, entry) in subtree {
+        let entry_path = match relative_path.as_os_str().is_empty() {
+            true => path.to_path_buf(),
+            false => path.join(relative_path),
+        };
+        entries.insert(entry_path, entry);
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        self.check_failures(&[path])?;
+        match self.entries.lock().unwrap().get(path) {
+            None => Err(io::Error::from(ErrorKind::NotFound)),
+            Some(MemoryEntry::Directory) => Ok(FileMetadata {
+                is_dir: true,
+                is_file: false,
+                is_symlink: false,
+                len: 0,
+                modified: UNIX_EPOCH,
+                inode: 0,
+            }),
+            Some(MemoryEntry::File { content, modified }) => Ok(FileMetadata {
+                is_dir: false,
+                is_file: true,
+                is_symlink: false,
+                len: content.len() as u64,
+                modified: *modified,
+                inode: 0,
+            }),
+        }
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        self.metadata(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        self.check_failures(&[path])?;
+        let entries = self.entries.lock().unwrap();
+        let directory = match path.as_os_str() == "." {
+            true => Path::new(""),
+            false => path,
+        };
+        if !directory.as_os_str().is_empty()
+            && entries.get(directory) != Some(&MemoryEntry::Directory)
+        {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        Ok(entries
+            .keys()
+            .filter(|entry_path| entry_path.parent() == Some(directory))
+            .filter_map(|entry_path| entry_path.file_name())
+            .map(|name| name.to_os_string())
+            .collect())
+    }
+
+    fn rename(&self, initial_path: &Path, target_path: &Path) -> io::Result<()> {
+        self.check_failures(&[initial_path, target_path])?;
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(initial_path) {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        if !has_parent(&entries, target_path) {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        if initial_path == target_path {
+            return Ok(());
+        }
+        if entries.get(target_path) == Some(&MemoryEntry::Directory) {
+            return Err(io::Error::from(ErrorKind::IsADirectory));
+        }
+        let subtree = take_subtree(&mut entries, initial_path);
+        entries.remove(target_path);
+        put_subtree(&mut entries, target_path, subtree);
+        Ok(())
+    }
+
+    fn rename_no_replace(&self, initial_path: &Path, target_path: &Path) -> io::Result<()> {
+        if self.entries.lock().unwrap().contains_key(target_path) {
+            self.check_failures(&[initial_path, target_path])?;
+            return Err(io::Error::from(ErrorKind::AlreadyExists));
+        }
+        self.rename(initial_path, target_path)
+    }
+
+    fn exchange(&self, first_path: &Path, second_path: &Path) -> io::Result<()> {
+        self.check_failures(&[first_path, second_path])?;
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(first_path) || !entries.contains_key(second_path) {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        let first_subtree = take_subtree(&mut entries, first_path);
+        let second_subtree = take_subtree(&mut entries, second_path);
+        put_subtree(&mut entries, second_path, first_subtree);
+        put_subtree(&mut entries, first_path, second_subtree);
+        Ok(())
+    }
+
+    fn copy(&self, initial_path: &Path, target_path: &Path) -> io::Result<u64> {
+        self.check_failures(&[initial_path, target_path])?;
+        let mut entries = self.entries.lock().unwrap();
+        let content = match entries.get(initial_path) {
+            None => return Err(io::Error::from(ErrorKind::NotFound)),
+            Some(MemoryEntry::Directory) => return Err(io::Error::from(ErrorKind::IsADirectory)),
+            Some(MemoryEntry::File { content, .. }) => content.clone(),
+        };
+        if !has_parent(&entries, target_path) {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        if entries.get(target_path) == Some(&MemoryEntry::Directory) {
+            return Err(io::Error::from(ErrorKind::IsADirectory));
+        }
+        let length = content.len() as u64;
+        entries.insert(
+            target_path.to_path_buf(),
+            MemoryEntry::File {
+                content,
+                modified: SystemTime::now(),
+            },
+        );
+        Ok(length)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.check_failures(&[path])?;
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(MemoryEntry::File { .. }) = entries.get(path) {
+            return Err(io::Error::from(ErrorKind::AlreadyExists));
+        }
+        // As on the real file system, a file can not be a parent directory
+        if path
+            .ancestors()
+            .skip(1)
+            .any(|parent| matches!(entries.get(parent), Some(MemoryEntry::File { .. })))
+        {
+            return Err(io::Error::from(ErrorKind::NotADirectory));
+        }
+        add_parents(&mut entries, path);
+        entries.insert(path.to_path_buf(), MemoryEntry::Directory);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.check_failures(&[path])?;
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(path) {
+            None => Err(io::Error::from(ErrorKind::NotFound)),
+            Some(MemoryEntry::Directory) => Err(io::Error::from(ErrorKind::IsADirectory)),
+            Some(MemoryEntry::File { .. }) => {
+                entries.remove(path);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_system::{FileSystem, MemoryFileSystem, StdFileSystem};
+    use glob::MatchOptions;
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_memory_file_system() {
+        let file_system = MemoryFileSystem::new();
+        file_system.add_file("root/a.txt", "a");
+        file_system.add_file("root/b.txt", "b");
+        file_system.add_file("root/inner/c.txt", "c");
+        assert_eq!(
+            file_system.glob("root/*.txt", MatchOptions::new()).unwrap(),
+            [PathBuf::from("root/a.txt"), PathBuf::from("root/b.txt")]
+        );
+        assert_eq!(
+            file_system
+                .rename_no_replace(Path::new("root/a.txt"), Path::new("root/b.txt"))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::AlreadyExists
+        );
+        assert!(file_system
+            .exchange(Path::new("root/a.txt"), Path::new("root/b.txt"))
+            .is_ok());
+        assert_eq!(file_system.read_file("root/a.txt"), Some("b".to_string()));
+        assert!(file_system
+            .rename(Path::new("root/inner"), Path::new("root/moved"))
+            .is_ok());
+        assert_eq!(
+            file_system.read_file("root/moved/c.txt"),
+            Some("c".to_string())
+        );
+        assert_eq!(
+            file_system
+                .rename(Path::new("root/a.txt"), Path::new("missing/a.txt"))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::NotFound
+        );
+        file_system.fail_on("root/b.txt", ErrorKind::PermissionDenied);
+        assert_eq!(
+            file_system
+                .rename(Path::new("root/b.txt"), Path::new("root/d.txt"))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::PermissionDenied
+        );
+        assert!(file_system
+            .rename(Path::new("root/a.txt"), Path::new("root/e.txt"))
+            .is_ok());
+        assert_eq!(
+            file_system.read_dir(Path::new("root")).unwrap(),
+            ["b.txt", "e.txt", "moved"]
+        );
+        assert_eq!(
+            file_system.files(),
+            [
+                PathBuf::from("root/b.txt"),
+                PathBuf::from("root/e.txt"),
+                PathBuf::from("root/moved/c.txt")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_glob_recursive() {
+        let file_system = MemoryFileSystem::new();
+        for name in [
+            "a/b.txt",
+            "a/c/d.txt",
+            "a/c/e/f.txt",
+            "a/.git/g.txt",
+            "a/h.jpg",
+        ] {
+            file_system.add_file(name, name);
+        }
+        assert_eq!(
+            file_system.glob("a/**/*.txt", MatchOptions::new()).unwrap(),
+            ["a/b.txt", "a/.git/g.txt", "a/c/d.txt", "a/c/e/f.txt"].map(PathBuf::from)
+        );
+        let no_hidden = MatchOptions {
+            require_literal_leading_dot: true,
+            ..MatchOptions::new()
+        };
+        assert_eq!(
+            file_system.glob("a/**/*.txt", no_hidden).unwrap(),
+            ["a/b.txt", "a/c/d.txt", "a/c/e/f.txt"].map(PathBuf::from)
+        );
+        assert_eq!(
+            file_system.glob("a/**", no_hidden).unwrap(),
+            ["a/c", "a/c/e"].map(PathBuf::from)
+        );
+        assert!(file_system.glob("a/b**", MatchOptions::new()).is_err());
+    }
+
+    #[test]
+    fn test_copy() {
+        let (_directory, file_systems) = file_systems_with_file("a.txt", "a");
+        for (file_system, root) in &file_systems {
+            let path = |name: &str| root.join(name);
+            assert_eq!(file_system.copy(&path("a.txt"), &path("b.txt")).unwrap(), 1);
+            assert_eq!(file_system.metadata(&path("a.txt")).unwrap().len, 1);
+            assert_eq!(file_system.metadata(&path("b.txt")).unwrap().len, 1);
+            assert_eq!(
+                file_system
+                    .copy(&path("missing.txt"), &path("c.txt"))
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::NotFound
+            );
+            assert!(file_system
+                .copy(&path("a.txt"), &path("missing/c.txt"))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_create_dir_all() {
+        let (_directory, file_systems) = file_systems_with_file("a.txt", "a");
+        for (file_system, root) in &file_systems {
+            let path = |name: &str| root.join(name);
+            assert!(file_system.create_dir_all(&path("b/c")).is_ok());
+            assert!(file_system.create_dir_all(&path("b/c")).is_ok());
+            assert!(file_system.metadata(&path("b")).unwrap().is_dir);
+            assert!(file_system.metadata(&path("b/c")).unwrap().is_dir);
+            assert!(file_system.create_dir_all(&path("a.txt/d")).is_err());
+        }
+    }
+
+    #[test]
+    fn test_remove_file() {
+        let (_directory, file_systems) = file_systems_with_file("a/b.txt", "b");
+        for (file_system, root) in &file_systems {
+            let path = |name: &str| root.join(name);
+            assert!(file_system.remove_file(&path("a/b.txt")).is_ok());
+            assert!(!file_system.exists(&path("a/b.txt")));
+            assert_eq!(
+                file_system
+                    .remove_file(&path("a/b.txt"))
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::NotFound
+            );
+            assert!(file_system.remove_file(&path("a")).is_err());
+            assert!(file_system.exists(&path("a")));
+        }
+    }
+
+    type RootedFileSystem = (Box<dyn FileSystem>, PathBuf);
+
+    /// The same file in memory and in a temporary directory, with the root to join names to
+    fn file_systems_with_file(name: &str, content: &str) -> (TempDir, Vec<RootedFileSystem>) {
+        let directory = tempfile::tempdir().unwrap();
+        let memory_file_system = MemoryFileSystem::new();
+        memory_file_system.add_file(Path::new("root").join(name), content);
+        let path = directory.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        let root = directory.path().to_path_buf();
+        (
+            directory,
+            vec![
+                (Box::new(memory_file_system), PathBuf::from("root")),
+                (Box::new(StdFileSystem), root),
+            ],
+        )
+    }
+}
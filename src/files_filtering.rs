@@ -0,0 +1,302 @@
+use crate::file_system::{FileMetadata, FileSystem, StdFileSystem};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum FilterParseError {
+    /// Custom error for filter values given in the wrong format
+    #[error("wrong file type '{0}', expected 'f' (file), 'd' (directory) or 'l' (symlink)")]
+    FileType(String),
+    #[error("wrong size '{0}', expected a number with optional suffix K, M, G or T (e.g. 10M)")]
+    Size(String),
+    #[error("wrong age '{0}', expected a number with suffix s, m, h, d or w (e.g. 7d)")]
+    Age(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileType {
+    File,
+    Directory,
+    Symlink,
+}
+
+impl FromStr for FileType {
+    type Err = FilterParseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "f" => Ok(FileType::File),
+            "d" => Ok(FileType::Directory),
+            "l" => Ok(FileType::Symlink),
+            _ => Err(FilterParseError::FileType(value.to_string())),
+        }
+    }
+}
+
+impl FileType {
+    fn of(metadata: &FileMetadata) -> Option<Self> {
+        if metadata.is_symlink {
+            Some(FileType::Symlink)
+        } else if metadata.is_dir {
+            Some(FileType::Directory)
+        } else if metadata.is_file {
+            Some(FileType::File)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses size in bytes with optional binary suffix: "512", "10K", "1M", "2GB", "1TiB"
+pub fn parse_size(value: &str) -> Result<u64, FilterParseError> {
+    let error = || FilterParseError::Size(value.to_string());
+    let digits_end = value
+        .find(|symbol: char| !symbol.is_ascii_digit())
+        .unwrap_or(value.len());
+    let number: u64 = value[..digits_end].parse().map_err(|_| error())?;
+    let multiplier: u64 = match value[digits_end..].to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(error()),
+    };
+    number.checked_mul(multiplier).ok_or_else(error)
+}
+
+/// Parses age like "30s", "15m", "12h", "7d" or "2w"
+pub fn parse_age(value: &str) -> Result<Duration, FilterParseError> {
+    let error = || FilterParseError::Age(value.to_string());
+    let digits_end = value
+        .find(|symbol: char| !symbol.is_ascii_digit())
+        .ok_or_else(error)?;
+    let number: u64 = value[..digits_end].parse().map_err(|_| error())?;
+    let seconds_in_unit: u64 = match &value[digits_end..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(error()),
+    };
+    number
+        .checked_mul(seconds_in_unit)
+        .map(Duration::from_secs)
+        .ok_or_else(error)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FileFilter {
+    /// Conditions that matched files must satisfy. Empty file_types and None limits accept everything
+    pub file_types: Vec<FileType>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub newer_than: Option<Duration>,
+    pub older_than: Option<Duration>,
+}
+
+impl FileFilter {
+    /// Checks whether the file satisfies all the conditions
+    ///
+    /// Symlinks are not followed: type, size and modification time are those of the link itself.
+    /// Files which metadata can not be read are not accepted
+    pub fn accepts(&self, file_system: &dyn FileSystem, filename: &Path, now: SystemTime) -> bool {
+        let Ok(metadata) = file_system.symlink_metadata(filename) else {
+            return false;
+        };
+        if !self.file_types.is_empty()
+            && !FileType::of(&metadata)
+                .is_some_and(|file_type| self.file_types.contains(&file_type))
+        {
+            return false;
+        }
+        if self
+            .min_size
+            .is_some_and(|min_size| metadata.len < min_size)
+            || self
+                .max_size
+                .is_some_and(|max_size| metadata.len > max_size)
+        {
+            return false;
+        }
+        if self.newer_than.is_none() && self.older_than.is_none() {
+            return true;
+        }
+        let age = now.duration_since(metadata.modified).unwrap_or_default();
+        !(self.newer_than.is_some_and(|newer_than| age > newer_than)
+            || self.older_than.is_some_and(|older_than| age < older_than))
+    }
+}
+
+/// Leaves only the files that satisfy the filter
+pub fn filter_files(filenames: Vec<PathBuf>, file_filter: &FileFilter) -> Vec<PathBuf> {
+    filter_files_in(&StdFileSystem, filenames, file_filter)
+}
+
+/// Leaves only the files that satisfy the filter, metadata is read from file_system
+pub fn filter_files_in(
+    file_system: &dyn FileSystem,
+    filenames: Vec<PathBuf>,
+    file_filter: &FileFilter,
+) -> Vec<PathBuf> {
+    if *file_filter == FileFilter::default() {
+        return filenames;
+    }
+    let now = SystemTime::now();
+    filenames
+        .into_iter()
+        .filter(|filename| file_filter.accepts(file_system, filename, now))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_system::{FileSystem, MemoryFileSystem};
+    use crate::files_filtering::{
+        filter_files, filter_files_in, parse_age, parse_size, FileFilter, FileType,
+        FilterParseError,
+    };
+    use std::fs::{create_dir_all, write, File};
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_parse_filter_values() {
+        assert_eq!("f".parse(), Ok(FileType::File));
+        assert_eq!("l".parse(), Ok(FileType::Symlink));
+        assert_eq!(
+            "x".parse::<FileType>(),
+            Err(FilterParseError::FileType("x".to_string()))
+        );
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10K"), Ok(10 * 1024));
+        assert_eq!(parse_size("2mb"), Ok(2 * 1024 * 1024));
+        assert_eq!(
+            parse_size("10Q"),
+            Err(FilterParseError::Size("10Q".to_string()))
+        );
+        assert_eq!(
+            parse_size("M"),
+            Err(FilterParseError::Size("M".to_string()))
+        );
+        assert_eq!(parse_age("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_age("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_age("7"), Err(FilterParseError::Age("7".to_string())));
+    }
+
+    #[test]
+    fn test_filter_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().to_str().unwrap().to_string();
+        let _ = create_dir_all(root.clone() + "/photos/album");
+        let _ = write(root.clone() + "/photos/small.jpg", [0u8; 10]);
+        let _ = write(root.clone() + "/photos/big.jpg", [0u8; 4096]);
+        let old_file = File::create(root.clone() + "/photos/old.jpg").unwrap();
+        let _ = old_file.set_modified(SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60));
+        #[cfg(unix)]
+        let _ = std::os::unix::fs::symlink("big.jpg", root.clone() + "/photos/link.jpg");
+        let path = |name: &str| PathBuf::from(root.clone() + name);
+        let filenames = vec![
+            path("/photos/album"),
+            path("/photos/big.jpg"),
+            path("/photos/link.jpg"),
+            path("/photos/old.jpg"),
+            path("/photos/small.jpg"),
+        ];
+
+        let only_files = FileFilter {
+            file_types: vec![FileType::File],
+            ..FileFilter::default()
+        };
+        assert_eq!(
+            filter_files(filenames.clone(), &only_files),
+            vec![
+                path("/photos/big.jpg"),
+                path("/photos/old.jpg"),
+                path("/photos/small.jpg"),
+            ]
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            filter_files(
+                filenames.clone(),
+                &FileFilter {
+                    file_types: vec![FileType::Directory, FileType::Symlink],
+                    ..FileFilter::default()
+                }
+            ),
+            vec![path("/photos/album"), path("/photos/link.jpg"),]
+        );
+        let sized_files = FileFilter {
+            file_types: vec![FileType::File],
+            min_size: Some(1),
+            max_size: Some(1024),
+            ..FileFilter::default()
+        };
+        assert_eq!(
+            filter_files(filenames.clone(), &sized_files),
+            vec![path("/photos/small.jpg")]
+        );
+        let old_files = FileFilter {
+            older_than: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            ..FileFilter::default()
+        };
+        assert_eq!(
+            filter_files(filenames.clone(), &old_files),
+            vec![path("/photos/old.jpg")]
+        );
+        let new_files = FileFilter {
+            file_types: vec![FileType::File],
+            newer_than: Some(Duration::from_secs(24 * 60 * 60)),
+            ..FileFilter::default()
+        };
+        assert_eq!(
+            filter_files(filenames, &new_files),
+            vec![path("/photos/big.jpg"), path("/photos/small.jpg"),]
+        );
+    }
+
+    #[test]
+    fn test_filter_files_in_memory() {
+        let file_system = MemoryFileSystem::new();
+        file_system.add_file("photos/small.jpg", "small");
+        file_system.add_file("photos/big.jpg", &"0".repeat(4096));
+        file_system.add_file("photos/old.jpg", "old");
+        let _ = file_system.create_dir_all(Path::new("photos/album"));
+        let now = SystemTime::now();
+        file_system.set_modified("photos/small.jpg", now);
+        file_system.set_modified("photos/big.jpg", now);
+        file_system.set_modified(
+            "photos/old.jpg",
+            now - Duration::from_secs(10 * 24 * 60 * 60),
+        );
+        let filenames: Vec<PathBuf> = ["album", "big.jpg", "missing.jpg", "old.jpg", "small.jpg"]
+            .iter()
+            .map(|name| PathBuf::from("photos").join(name))
+            .collect();
+        let directories = FileFilter {
+            file_types: vec![FileType::Directory],
+            ..FileFilter::default()
+        };
+        assert_eq!(
+            filter_files_in(&file_system, filenames.clone(), &directories),
+            [PathBuf::from("photos/album")]
+        );
+        let new_small_files = FileFilter {
+            file_types: vec![FileType::File],
+            max_size: Some(1024),
+            newer_than: Some(Duration::from_secs(24 * 60 * 60)),
+            ..FileFilter::default()
+        };
+        assert_eq!(
+            filter_files_in(&file_system, filenames.clone(), &new_small_files),
+            [PathBuf::from("photos/small.jpg")]
+        );
+        file_system.fail_on("photos/small.jpg", ErrorKind::PermissionDenied);
+        assert!(!new_small_files.accepts(&file_system, Path::new("photos/small.jpg"), now));
+    }
+}
@@ -1,148 +1,1211 @@
-use glob::glob;
-use std::env::current_dir;
-use std::fs::rename;
+use crate::backups::BackupStyle;
+use crate::errors::MmvError;
+use crate::file_system::{FileSystem, StdFileSystem};
+use crate::normalization::NormalForm;
+use crate::os_strings::{name_from_bytes, with_suffix};
+use crate::verbosity::{log, Verbosity};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{read, read_to_string};
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 use thiserror::Error;
 
-#[derive(Debug, PartialEq, Error)]
-pub enum MassMoveError {
-    /// Custom error handling errors of mass_move
-    #[error("mmv: Not able to replace existing file: '{0}'")]
-    ReplaceError(String),
-    #[error("mmv: Not able to move file '{0}'. Attempt to reach restricted directory or another filesystem")]
-    PermissionError(String),
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// What to do when the target file already exists
+    #[default]
+    Fail,
+    Skip,
+    Overwrite,
+    OverwriteIfOlder,
+    #[serde(rename = "rename")]
+    RenameWithSuffix,
+    Backup,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "fail" => Ok(ConflictPolicy::Fail),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "overwrite-if-older" => Ok(ConflictPolicy::OverwriteIfOlder),
+            "rename" => Ok(ConflictPolicy::RenameWithSuffix),
+            "backup" => Ok(ConflictPolicy::Backup),
+            _ => Err(format!(
+                "wrong policy '{}', expected one of: fail, skip, overwrite, overwrite-if-older, rename, backup",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MoveOptions {
+    /// Settings of mass_move. If backup_style is set, every overwritten file is kept as a backup
+    /// (ConflictPolicy::Backup uses the default style 'name~' when it is not set).
+    /// If swap is set, existing targets are exchanged with their sources instead.
+    /// If keep_going is set, pairs that can not be moved are reported as Failed, the rest are moved.
+    /// confirmed_overwrites tells for every pair whether its target is overwritten whatever
+    /// the policy is (the user agreed to it, see confirm_moves), it may be shorter than the pairs
+    pub conflict_policy: ConflictPolicy,
+    pub backup_style: Option<BackupStyle>,
+    pub swap: bool,
+    pub keep_going: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub confirmed_overwrites: Vec<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoveStatus {
+    /// How the file was moved
+    Moved,
+    Overwritten,
+    Skipped,
+    /// Target existed, so the file was moved to the given name with suffix
+    Renamed(PathBuf),
+    /// Target existed, it was kept under the given name and then overwritten
+    BackedUp(PathBuf),
+    /// Target existed, it took the place of the moved file
+    Swapped,
+    /// File was not moved because of the error (only with MoveOptions::keep_going)
+    Failed(Arc<MmvError>),
+}
+
+impl MoveStatus {
+    /// Tells whether the file is left where it was
+    pub fn is_left(&self) -> bool {
+        matches!(self, MoveStatus::Skipped | MoveStatus::Failed(_))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveReport {
+    pub initial_filename: PathBuf,
+    pub target_filename: PathBuf,
+    pub status: MoveStatus,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    /// Files to move with their new names, in the same order
+    pub initial_filenames: Vec<PathBuf>,
+    pub target_filenames: Vec<PathBuf>,
+}
+
+impl Plan {
+    pub fn new(initial_filenames: Vec<PathBuf>, target_filenames: Vec<PathBuf>) -> Self {
+        Plan {
+            initial_filenames,
+            target_filenames,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.initial_filenames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.initial_filenames.is_empty()
+    }
+
+    /// Pairs of old and new names
+    pub fn pairs(&self) -> impl Iterator<Item = (&PathBuf, &PathBuf)> {
+        self.initial_filenames.iter().zip(&self.target_filenames)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Executor<F: FileSystem = StdFileSystem> {
+    /// Moves the files of a Plan with the given options in the file system
+    options: MoveOptions,
+    file_system: F,
+}
+
+impl Executor {
+    /// ## Example
+    /// ```
+    /// use mmv::{ConflictPolicy, Executor, MoveOptions, Plan};
+    ///
+    /// let executor = Executor::new(MoveOptions {
+    ///     conflict_policy: ConflictPolicy::Skip,
+    ///     ..MoveOptions::default()
+    /// });
+    /// let plan = Plan::new(vec!["a.txt".into()], vec!["b.txt".into()]);
+    /// // Nothing is moved, the steps are only planned
+    /// let move_plan = executor.dry_run(&plan).unwrap();
+    /// assert_eq!(move_plan.steps.len(), 1);
+    /// ```
+    pub fn new(options: MoveOptions) -> Self {
+        Executor::with_file_system(options, StdFileSystem)
+    }
+}
+
+impl<F: FileSystem> Executor<F> {
+    /// ## Example
+    /// ```
+    /// use mmv::file_system::MemoryFileSystem;
+    /// use mmv::{Executor, MoveOptions, MoveStatus, Plan};
+    /// use std::io::ErrorKind;
+    ///
+    /// let file_system = MemoryFileSystem::new();
+    /// file_system.add_file("photos/a.jpg", "");
+    /// file_system.add_file("photos/b.jpg", "");
+    /// file_system.fail_on("photos/b.jpg", ErrorKind::PermissionDenied);
+    /// let executor = Executor::with_file_system(
+    ///     MoveOptions { keep_going: true, ..MoveOptions::default() },
+    ///     file_system,
+    /// );
+    /// let plan = Plan::new(
+    ///     vec!["photos/a.jpg".into(), "photos/b.jpg".into()],
+    ///     vec!["photos/1.jpg".into(), "photos/2.jpg".into()],
+    /// );
+    /// let reports = executor.execute(&plan).unwrap();
+    /// assert_eq!(reports[0].status, MoveStatus::Moved);
+    /// assert!(matches!(reports[1].status, MoveStatus::Failed(_)));
+    /// ```
+    pub fn with_file_system(options: MoveOptions, file_system: F) -> Self {
+        Executor {
+            options,
+            file_system,
+        }
+    }
+
+    pub fn file_system(&self) -> &F {
+        &self.file_system
+    }
+
+    pub fn options(&self) -> &MoveOptions {
+        &self.options
+    }
+
+    /// Checks the conflicts and orders the moves as execute does, without touching the files
+    pub fn dry_run(&self, plan: &Plan) -> Result<MovePlan, MmvError> {
+        plan_moves_in(
+            &self.file_system,
+            &plan.initial_filenames,
+            &plan.target_filenames,
+            &self.options,
+        )
+    }
+
+    /// Moves the files, see mass_move
+    pub fn execute(&self, plan: &Plan) -> Result<Vec<MoveReport>, MmvError> {
+        mass_move_in(
+            &self.file_system,
+            &plan.initial_filenames,
+            &plan.target_filenames,
+            &self.options,
+        )
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ExcludeError {
+    /// Custom error for exclude patterns that can not be applied
+    #[error("mmv: Invalid exclude pattern '{0}'")]
+    InvalidPattern(String),
+    #[error("mmv: Not able to read exclude patterns from file '{0}'")]
+    ReadError(String),
 }
 
 #[derive(Error, Debug, PartialEq)]
-#[error("mmv: Files for pattern '{template_name}' not found")]
-pub struct NoFilesError {
-    /// Custom error for catching no suitable files for template
-    template_name: String,
+pub enum MapError {
+    /// Custom error for mapping files with explicit old and new names
+    #[error("mmv: Not able to read map file '{0}'")]
+    ReadError(String),
+    #[error("mmv: Line {line} of map file '{map_file}' is not a pair of old and new names")]
+    FormatError { map_file: String, line: usize },
 }
 
-pub fn get_files_by_template(template: &str) -> Result<Vec<String>, NoFilesError> {
+pub fn get_files_by_template(
+    template: &str,
+    match_options: MatchOptions,
+) -> Result<Vec<PathBuf>, MmvError> {
+    get_files_by_template_in(&StdFileSystem, template, match_options, None)
+}
+
+pub fn get_files_by_template_in(
+    file_system: &dyn FileSystem,
+    template: &str,
+    match_options: MatchOptions,
+    normal_form: Option<NormalForm>,
+) -> Result<Vec<PathBuf>, MmvError> {
     /*
     Gets files names that suit the given template ('*' stands for any chars sequence in filename)
+    Matching rules (case sensitivity, hidden files) are taken from match_options
+    With normal_form the template and the names are compared in that Unicode normal form
+    Special entries '.' and '..' (glob yields them for templates like '.*') are never taken.
+    Names are kept exactly as they are, even if they are not valid UTF-8
     */
-    let all_template_files: Vec<PathBuf> = glob(template).unwrap().filter_map(Result::ok).collect();
-    if all_template_files.len() == 0 {
-        Err(NoFilesError {
-            template_name: template.to_string(),
+    let all_template_files: Vec<PathBuf> = file_system
+        .glob_normalized(template, match_options, normal_form)
+        .map_err(|source| MmvError::InvalidPattern {
+            pattern: template.to_string(),
+            source,
+        })?
+        .into_iter()
+        .filter(|path| {
+            !matches!(
+                path.to_string_lossy().rsplit('/').next(),
+                Some(".") | Some("..")
+            )
         })
+        .collect();
+    if all_template_files.is_empty() {
+        Err(MmvError::NoFiles(template.to_string()))
     } else {
-        Ok(all_template_files
+        for filename in &all_template_files {
+            log(Verbosity::Verbose, || {
+                format!("File '{}' matches '{}'", filename.display(), template)
+            });
+        }
+        Ok(all_template_files)
+    }
+}
+
+/// Reads list of filenames (e.g. output of 'find' or 'git ls-files') separated by newlines or,
+/// if nul_separated is true, by NUL bytes (output of 'find -print0'). Empty entries are skipped.
+/// Names are taken byte by byte, they do not have to be valid UTF-8
+pub fn read_files_list<R: Read>(mut reader: R, nul_separated: bool) -> io::Result<Vec<PathBuf>> {
+    let mut content: Vec<u8> = vec![];
+    reader.read_to_end(&mut content)?;
+    let separator = if nul_separated { b'\0' } else { b'\n' };
+    Ok(content
+        .split(|byte| *byte == separator)
+        .filter(|filename| !filename.is_empty())
+        .map(|filename| PathBuf::from(name_from_bytes(filename.to_vec())))
+        .collect())
+}
+
+/// Reads exclude patterns from file, one per line. Empty lines and lines starting with '#' are skipped
+pub fn read_exclude_patterns(exclude_file: &str) -> Result<Vec<String>, ExcludeError> {
+    let content = read_to_string(exclude_file)
+        .map_err(|_| ExcludeError::ReadError(exclude_file.to_string()))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Removes filenames that match any of exclude_patterns (glob syntax)
+///
+/// A pattern is checked against the whole path and against the file name alone,
+/// so both '*_final.*' and 'reports/*_final.*' exclude 'reports/report_1_final.pdf'
+pub fn exclude_files(
+    filenames: Vec<PathBuf>,
+    exclude_patterns: &[String],
+    match_options: MatchOptions,
+) -> Result<Vec<PathBuf>, ExcludeError> {
+    let compiled_patterns = exclude_patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|_| ExcludeError::InvalidPattern(pattern.to_string()))
+        })
+        .collect::<Result<Vec<Pattern>, ExcludeError>>()?;
+    // Excluding hidden files must work even if they are not matched by '*' in the choice template
+    let exclude_options = MatchOptions {
+        require_literal_leading_dot: false,
+        ..match_options
+    };
+    Ok(filenames
+        .into_iter()
+        .filter(|filename| {
+            // Patterns are text, so names that are not valid UTF-8 are matched in their lossy form
+            let path = filename.to_string_lossy();
+            let name = filename
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            let excluding_pattern = compiled_patterns.iter().find(|pattern| {
+                pattern.matches_with(&path, exclude_options)
+                    || pattern.matches_with(&name, exclude_options)
+            });
+            if let Some(pattern) = excluding_pattern {
+                log(Verbosity::Verbose, || {
+                    format!("File '{}' is excluded by '{}'", path, pattern)
+                });
+            }
+            excluding_pattern.is_none()
+        })
+        .collect())
+}
+
+/// Reads explicit pairs of old and new names from CSV or TSV file
+///
+/// Every line holds two fields: old name and new name. Fields are separated by tab if the file
+/// has '.tsv' extension or its first record contains tab, otherwise by comma. CSV fields may be
+/// quoted with '"' ('""' inside quotes stands for '"'). Empty lines, lines starting with '#' and
+/// header line (old,new / source,target / from,to) are skipped.
+/// The file is read as bytes, so names that are not valid UTF-8 (e.g. Latin-1 or CP1251) are
+/// taken exactly as they are
+pub fn read_moves_map(map_file: &str) -> Result<(Vec<PathBuf>, Vec<PathBuf>), MapError> {
+    let content = read(map_file).map_err(|_| MapError::ReadError(map_file.to_string()))?;
+    let records: Vec<(usize, &[u8])> = content
+        .split(|byte| *byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with(b"#"))
+        .collect();
+    let delimiter = match records.first() {
+        _ if map_file.to_ascii_lowercase().ends_with(".tsv") => b'\t',
+        Some((_, line)) if line.contains(&b'\t') => b'\t',
+        _ => b',',
+    };
+    let mut initial_filenames: Vec<PathBuf> = vec![];
+    let mut target_filenames: Vec<PathBuf> = vec![];
+    for (record_index, (line_index, line)) in records.into_iter().enumerate() {
+        let format_error = || MapError::FormatError {
+            map_file: map_file.to_string(),
+            line: line_index + 1,
+        };
+        let fields = split_record(line, delimiter).ok_or_else(format_error)?;
+        let [old_name, new_name] = <[Vec<u8>; 2]>::try_from(fields).map_err(|_| format_error())?;
+        let header = (old_name.to_ascii_lowercase(), new_name.to_ascii_lowercase());
+        let is_header = matches!(
+            (header.0.as_slice(), header.1.as_slice()),
+            (b"old", b"new") | (b"source", b"target") | (b"from", b"to")
+        );
+        if record_index == 0 && is_header {
+            continue;
+        }
+        if old_name.is_empty() || new_name.is_empty() {
+            return Err(format_error());
+        }
+        initial_filenames.push(PathBuf::from(name_from_bytes(old_name)));
+        target_filenames.push(PathBuf::from(name_from_bytes(new_name)));
+    }
+    Ok((initial_filenames, target_filenames))
+}
+
+/// Splits one CSV/TSV record into fields, None if quotes are not balanced
+fn split_record(line: &[u8], delimiter: u8) -> Option<Vec<Vec<u8>>> {
+    let mut fields: Vec<Vec<u8>> = vec![];
+    let mut field: Vec<u8> = vec![];
+    let mut symbols = line.iter().copied().peekable();
+    let mut quoted = false;
+    while let Some(symbol) = symbols.next() {
+        match symbol {
+            b'"' if quoted && symbols.peek() == Some(&b'"') => {
+                symbols.next();
+                field.push(b'"');
+            }
+            b'"' if quoted => quoted = false,
+            b'"' if field.is_empty() => quoted = true,
+            _ if symbol == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(symbol),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+/// Orders the moves so that no file is overwritten by another one from the same batch
+///
+/// If some target is a source of another pair, that pair is moved first (chains 'a -> b, b -> c').
+/// Cycles ('a -> b, b -> a') are broken by moving one of the files to a temporary name first,
+/// so there may be more steps than pairs
+pub fn order_moves(
+    file_system: &dyn FileSystem,
+    initial_filenames: &[PathBuf],
+    target_filenames: &[PathBuf],
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut pending: Vec<(PathBuf, PathBuf)> = initial_filenames
+        .iter()
+        .cloned()
+        .zip(target_filenames.iter().cloned())
+        .collect();
+    let mut taken_names: HashSet<PathBuf> = initial_filenames
+        .iter()
+        .chain(target_filenames.iter())
+        .cloned()
+        .collect();
+    let mut ordered_moves: Vec<(PathBuf, PathBuf)> = vec![];
+    while !pending.is_empty() {
+        let pending_sources: HashMap<&PathBuf, usize> = pending
+            .iter()
+            .enumerate()
+            .map(|(index, (source, _))| (source, index))
+            .collect();
+        let ready_move = pending
             .iter()
-            .map(|filename| filename.to_string_lossy().to_string())
-            .collect())
-    }
-}
-
-pub fn mass_move(
-    initial_filenames: &Vec<String>,
-    target_filenames: &Vec<String>,
-    force_rewrite: bool,
-) -> Result<(), MassMoveError> {
-    for i in 0..target_filenames.len() {
-        if Path::new(&target_filenames[i]).exists() && !force_rewrite {
-            return Err(MassMoveError::ReplaceError(target_filenames[i].to_string()));
-        }
-        let _ = match rename(&initial_filenames[i], &target_filenames[i]) {
-            Ok(_) => {}
-            Err(_) => {
-                let current_directory = current_dir().unwrap().to_string_lossy().to_string();
-                let _ = match rename(
-                    current_directory.clone() + "/" + &initial_filenames[i],
-                    current_directory.clone() + "/" + &target_filenames[i],
-                ) {
-                    Ok(_) => {}
-                    Err(_) => {
-                        return Err(MassMoveError::PermissionError(
-                            current_directory.clone() + "/" + &target_filenames[i].to_string(),
-                        ));
+            .position(|(source, target)| source == target || !pending_sources.contains_key(target));
+        match ready_move {
+            Some(index) => ordered_moves.push(pending.remove(index)),
+            None => {
+                let source = pending[0].0.clone();
+                let temporary_name = temporary_name(file_system, &source, &taken_names);
+                taken_names.insert(temporary_name.clone());
+                ordered_moves.push((source, temporary_name.clone()));
+                pending[0].0 = temporary_name;
+            }
+        }
+    }
+    ordered_moves
+}
+
+/// Builds free name 'source.mmv-tmpN' to keep the file under for a while
+pub fn temporary_name(
+    file_system: &dyn FileSystem,
+    source: &Path,
+    taken_names: &HashSet<PathBuf>,
+) -> PathBuf {
+    (1..)
+        .map(|number| with_suffix(source, &format!(".mmv-tmp{}", number)))
+        .find(|name| !taken_names.contains(name) && !file_system.exists(name))
+        .unwrap()
+}
+
+/// For every pair tells whether its target is an existing file that would be overwritten.
+/// Targets that are going to be moved away in the same batch are not counted
+pub fn occupied_targets(
+    file_system: &dyn FileSystem,
+    initial_filenames: &[PathBuf],
+    target_filenames: &[PathBuf],
+) -> Vec<bool> {
+    let sources: HashSet<&PathBuf> = initial_filenames.iter().collect();
+    (0..target_filenames.len())
+        .map(|i| {
+            let moved_away = initial_filenames[i] != target_filenames[i]
+                && sources.contains(&target_filenames[i]);
+            !moved_away && file_system.exists(&target_filenames[i])
+        })
+        .collect()
+}
+
+/// Builds free name with number suffix before extension: 'photo.jpg' -> 'photo (1).jpg'
+fn suffixed_name(
+    file_system: &dyn FileSystem,
+    target_filename: &Path,
+    taken_names: &HashSet<PathBuf>,
+) -> PathBuf {
+    let stem = target_filename.file_stem().unwrap_or_default();
+    let extension = target_filename.extension();
+    (1..)
+        .map(|number| {
+            let mut name = stem.to_os_string();
+            name.push(format!(" ({})", number));
+            if let Some(extension) = extension {
+                name.push(".");
+                name.push(extension);
+            }
+            target_filename.with_file_name(name)
+        })
+        .find(|candidate| !taken_names.contains(candidate) && !file_system.exists(candidate))
+        .unwrap()
+}
+
+/// Decides what to do with every pair which target exists, according to move_options.
+/// Targets must be unique. Skipped pairs stay in the reports, but are not moved.
+/// With keep_going the pairs in conflict are reported as Failed instead of returning the error
+fn resolve_conflicts(
+    file_system: &dyn FileSystem,
+    initial_filenames: &[PathBuf],
+    target_filenames: &[PathBuf],
+    move_options: &MoveOptions,
+) -> Result<Vec<MoveReport>, MmvError> {
+    let mut seen_targets: HashSet<&PathBuf> = HashSet::new();
+    let mut collided_targets: HashSet<&PathBuf> = HashSet::new();
+    for target_filename in target_filenames {
+        if !seen_targets.insert(target_filename) {
+            if !move_options.keep_going {
+                return Err(MmvError::Collision(target_filename.clone()));
+            }
+            collided_targets.insert(target_filename);
+        }
+    }
+    let mut reports: Vec<MoveReport> = initial_filenames
+        .iter()
+        .zip(target_filenames)
+        .map(|(initial_filename, target_filename)| MoveReport {
+            initial_filename: initial_filename.clone(),
+            target_filename: target_filename.clone(),
+            status: match collided_targets.contains(target_filename) {
+                true => MoveStatus::Failed(Arc::new(MmvError::Collision(target_filename.clone()))),
+                false => MoveStatus::Moved,
+            },
+        })
+        .collect();
+    let mut taken_names: HashSet<PathBuf> = initial_filenames
+        .iter()
+        .chain(target_filenames)
+        .cloned()
+        .collect();
+    // Skipping a file may make occupied the target of another one, so conflicts are
+    // checked again until nothing changes
+    loop {
+        let moving: Vec<&MoveReport> = reports
+            .iter()
+            .filter(|report| !report.status.is_left())
+            .collect();
+        let moving_sources: Vec<PathBuf> = moving
+            .iter()
+            .map(|report| report.initial_filename.clone())
+            .collect();
+        let moving_targets: Vec<PathBuf> = moving
+            .iter()
+            .map(|report| report.target_filename.clone())
+            .collect();
+        let occupied: HashSet<PathBuf> =
+            occupied_targets(file_system, &moving_sources, &moving_targets)
+                .into_iter()
+                .zip(moving_targets)
+                .filter_map(|(is_occupied, target_filename)| is_occupied.then_some(target_filename))
+                .collect();
+        let mut skipped_any = false;
+        for (index, report) in reports.iter_mut().enumerate() {
+            if report.status != MoveStatus::Moved || !occupied.contains(&report.target_filename) {
+                continue;
+            }
+            let conflict_policy = match move_options.confirmed_overwrites.get(index) {
+                Some(true) => ConflictPolicy::Overwrite,
+                _ => move_options.conflict_policy,
+            };
+            if conflict_policy == ConflictPolicy::Fail {
+                let error = MmvError::TargetExists(report.target_filename.clone());
+                if !move_options.keep_going {
+                    return Err(error);
+                }
+                report.status = MoveStatus::Failed(Arc::new(error));
+                skipped_any = true;
+                continue;
+            }
+            report.status = match conflict_policy {
+                _ if report.initial_filename == report.target_filename => MoveStatus::Skipped,
+                ConflictPolicy::Skip => MoveStatus::Skipped,
+                ConflictPolicy::OverwriteIfOlder => {
+                    let modified = |filename: &Path| {
+                        file_system
+                            .metadata(filename)
+                            .map(|metadata| metadata.modified)
+                    };
+                    match (
+                        modified(&report.initial_filename),
+                        modified(&report.target_filename),
+                    ) {
+                        (Ok(source_time), Ok(target_time)) if target_time < source_time => {
+                            MoveStatus::Overwritten
+                        }
+                        _ => MoveStatus::Skipped,
                     }
-                };
+                }
+                ConflictPolicy::RenameWithSuffix => {
+                    let new_name =
+                        suffixed_name(file_system, &report.target_filename, &taken_names);
+                    taken_names.insert(new_name.clone());
+                    MoveStatus::Renamed(new_name)
+                }
+                ConflictPolicy::Backup => {
+                    let backup_style = move_options.backup_style.clone().unwrap_or_default();
+                    MoveStatus::BackedUp(
+                        backup_style.backup_name(file_system, &report.target_filename),
+                    )
+                }
+                ConflictPolicy::Overwrite | ConflictPolicy::Fail => MoveStatus::Overwritten,
+            };
+            if let (MoveStatus::Overwritten, Some(backup_style)) =
+                (&report.status, &move_options.backup_style)
+            {
+                report.status = MoveStatus::BackedUp(
+                    backup_style.backup_name(file_system, &report.target_filename),
+                );
             }
+            skipped_any |= report.status == MoveStatus::Skipped;
+        }
+        if !skipped_any {
+            return Ok(reports);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepKind {
+    /// How one step of the plan changes the file system. Move is a rename that fails
+    /// if the target exists
+    Move,
+    /// Rename over the existing target
+    Replace,
+    /// Exchange of two existing files
+    Exchange,
+    /// Rename of the existing target to its backup name, over the older backup
+    Backup,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveStep {
+    /// One rename of the plan. pair_index tells which pair of the reports it belongs to
+    pub kind: StepKind,
+    pub initial_filename: PathBuf,
+    pub target_filename: PathBuf,
+    pub pair_index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovePlan {
+    /// Expected report for every pair and the steps to do in the given order. A pair may take
+    /// several steps (backup of the target, moves through temporary names in cycles)
+    pub reports: Vec<MoveReport>,
+    pub steps: Vec<MoveStep>,
+}
+
+/// Decides what mass_move is going to do without touching any file
+///
+/// All the conflicts are checked here: targets must be unique, existing targets
+/// are handled according to move_options (ConflictPolicy::Fail stops with TargetExists).
+/// Targets that are going to be moved away in the same batch do not count as existing.
+/// With MoveOptions::keep_going the pairs in conflict are reported as Failed instead
+pub fn plan_moves<P: AsRef<Path>>(
+    initial_filenames: &[P],
+    target_filenames: &[P],
+    move_options: &MoveOptions,
+) -> Result<MovePlan, MmvError> {
+    plan_moves_in(
+        &StdFileSystem,
+        initial_filenames,
+        target_filenames,
+        move_options,
+    )
+}
+
+/// Does the same as plan_moves, checking the files in the given file system
+pub fn plan_moves_in<P: AsRef<Path>>(
+    file_system: &dyn FileSystem,
+    initial_filenames: &[P],
+    target_filenames: &[P],
+    move_options: &MoveOptions,
+) -> Result<MovePlan, MmvError> {
+    let to_paths = |filenames: &[P]| -> Vec<PathBuf> {
+        filenames
+            .iter()
+            .map(|filename| filename.as_ref().to_path_buf())
+            .collect()
+    };
+    let (initial_filenames, target_filenames) =
+        (&to_paths(initial_filenames), &to_paths(target_filenames));
+    if move_options.swap {
+        return plan_swaps(
+            file_system,
+            initial_filenames,
+            target_filenames,
+            move_options.keep_going,
+        );
+    }
+    let reports = resolve_conflicts(
+        file_system,
+        initial_filenames,
+        target_filenames,
+        move_options,
+    )?;
+    let mut backups: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut overwritten_targets: HashSet<PathBuf> = HashSet::new();
+    // Every step of the ordered moves is traced back to its pair by the final target, and
+    // steps to temporary names by the source
+    let mut indices_by_target: HashMap<PathBuf, usize> = HashMap::new();
+    let mut indices_by_source: HashMap<PathBuf, usize> = HashMap::new();
+    let mut moving_sources: Vec<PathBuf> = vec![];
+    let mut moving_targets: Vec<PathBuf> = vec![];
+    for (index, report) in reports.iter().enumerate() {
+        let final_target = match &report.status {
+            MoveStatus::Skipped | MoveStatus::Failed(_) => continue,
+            MoveStatus::Renamed(new_name) => new_name.clone(),
+            MoveStatus::BackedUp(backup_name) => {
+                backups.insert(report.target_filename.clone(), backup_name.clone());
+                report.target_filename.clone()
+            }
+            MoveStatus::Overwritten => {
+                overwritten_targets.insert(report.target_filename.clone());
+                report.target_filename.clone()
+            }
+            MoveStatus::Moved => report.target_filename.clone(),
+            MoveStatus::Swapped => unreachable!("files are swapped only by plan_swaps"),
+        };
+        indices_by_target.insert(final_target.clone(), index);
+        indices_by_source.insert(report.initial_filename.clone(), index);
+        moving_sources.push(report.initial_filename.clone());
+        moving_targets.push(final_target);
+    }
+    let mut steps: Vec<MoveStep> = vec![];
+    for (initial_filename, target_filename) in
+        order_moves(file_system, &moving_sources, &moving_targets)
+    {
+        let pair_index = indices_by_target
+            .get(&target_filename)
+            .or_else(|| indices_by_source.get(&initial_filename))
+            .copied()
+            .unwrap();
+        if let Some(backup_name) = backups.get(&target_filename) {
+            // Simple backups replace the previous backup, as in 'mv --backup'
+            steps.push(MoveStep {
+                kind: StepKind::Backup,
+                initial_filename: target_filename.clone(),
+                target_filename: backup_name.clone(),
+                pair_index,
+            });
+        }
+        let kind = match overwritten_targets.contains(&target_filename) {
+            true => StepKind::Replace,
+            false => StepKind::Move,
         };
+        steps.push(MoveStep {
+            kind,
+            initial_filename,
+            target_filename,
+            pair_index,
+        });
     }
-    Ok(())
+    Ok(MovePlan { reports, steps })
+}
+
+/// Plans to exchange every file with its target if the target exists, to move it otherwise
+///
+/// Every name may take part in one pair only, as exchanges do not chain like moves do,
+/// otherwise Collision error is returned (with keep_going only those pairs are reported
+/// as Failed). Pairs with equal names are skipped
+fn plan_swaps(
+    file_system: &dyn FileSystem,
+    initial_filenames: &[PathBuf],
+    target_filenames: &[PathBuf],
+    keep_going: bool,
+) -> Result<MovePlan, MmvError> {
+    let mut seen_names: HashSet<&PathBuf> = HashSet::new();
+    let mut collided_names: HashSet<&PathBuf> = HashSet::new();
+    for (initial_filename, target_filename) in initial_filenames.iter().zip(target_filenames) {
+        if initial_filename == target_filename {
+            continue;
+        }
+        for filename in [initial_filename, target_filename] {
+            if !seen_names.insert(filename) {
+                if !keep_going {
+                    return Err(MmvError::Collision(filename.to_path_buf()));
+                }
+                collided_names.insert(filename);
+            }
+        }
+    }
+    let mut plan = MovePlan {
+        reports: vec![],
+        steps: vec![],
+    };
+    for (pair_index, (initial_filename, target_filename)) in
+        initial_filenames.iter().zip(target_filenames).enumerate()
+    {
+        let collided_name = [initial_filename, target_filename]
+            .into_iter()
+            .find(|filename| collided_names.contains(filename));
+        let (status, kind) = if let Some(collided_name) = collided_name {
+            let error = MmvError::Collision(collided_name.to_path_buf());
+            (MoveStatus::Failed(Arc::new(error)), None)
+        } else if initial_filename == target_filename {
+            (MoveStatus::Skipped, None)
+        } else if file_system.exists(target_filename) {
+            (MoveStatus::Swapped, Some(StepKind::Exchange))
+        } else {
+            (MoveStatus::Moved, Some(StepKind::Move))
+        };
+        if let Some(kind) = kind {
+            plan.steps.push(MoveStep {
+                kind,
+                initial_filename: initial_filename.clone(),
+                target_filename: target_filename.clone(),
+                pair_index,
+            });
+        }
+        plan.reports.push(MoveReport {
+            initial_filename: initial_filename.clone(),
+            target_filename: target_filename.clone(),
+            status,
+        });
+    }
+    Ok(plan)
+}
+
+/// Moves every file to its target name according to plan_moves
+///
+/// Only the targets reported as Overwritten are replaced, any other target that appears while
+/// moving (e.g. created by another process) stops mass_move with TargetExists.
+/// If some files were moved before the error, it is returned as MmvError::Interrupted with the
+/// reports of the pairs done before it.
+/// With MoveOptions::keep_going nothing stops mass_move: every pair that can not be moved
+/// is reported as Failed and the rest are moved.
+/// If a step of a cycle fails, the done steps of the cycle are undone, so no file is left
+/// under a temporary name (MmvError::Stranded tells where it is if undoing fails too), and
+/// all the pairs of the cycle fail. The same way, if the file can not be moved to a target that
+/// is already backed up, the backup is renamed back to the target.
+/// Returns report for every pair in the given order
+pub fn mass_move<P: AsRef<Path>>(
+    initial_filenames: &[P],
+    target_filenames: &[P],
+    move_options: &MoveOptions,
+) -> Result<Vec<MoveReport>, MmvError> {
+    mass_move_in(
+        &StdFileSystem,
+        initial_filenames,
+        target_filenames,
+        move_options,
+    )
+}
+
+/// Does the same as mass_move in the given file system
+pub fn mass_move_in<P: AsRef<Path>>(
+    file_system: &dyn FileSystem,
+    initial_filenames: &[P],
+    target_filenames: &[P],
+    move_options: &MoveOptions,
+) -> Result<Vec<MoveReport>, MmvError> {
+    let MovePlan { mut reports, steps } = plan_moves_in(
+        file_system,
+        initial_filenames,
+        target_filenames,
+        move_options,
+    )?;
+    // Cycle starts with the move to a temporary name and ends with the move from it
+    let step_indices: HashMap<&PathBuf, usize> = steps
+        .iter()
+        .enumerate()
+        .map(|(index, step)| (&step.initial_filename, index))
+        .collect();
+    let cycle_ends: Vec<Option<usize>> = steps
+        .iter()
+        .enumerate()
+        .map(|(index, step)| {
+            step_indices
+                .get(&step.target_filename)
+                .copied()
+                .filter(|&end| end > index)
+        })
+        .collect();
+    let mut last_steps: Vec<Option<usize>> = vec![None; reports.len()];
+    for (index, step) in steps.iter().enumerate() {
+        last_steps[step.pair_index] = Some(index);
+    }
+    let mut cycle: Option<(usize, usize)> = None;
+    for (index, step) in steps.iter().enumerate() {
+        if matches!(reports[step.pair_index].status, MoveStatus::Failed(_)) {
+            continue;
+        }
+        if let Some(end) = cycle_ends[index] {
+            cycle = Some((index, end));
+        }
+        let error = match run_step(file_system, step) {
+            Ok(_) => {
+                if cycle.is_some_and(|(_, end)| end == index) {
+                    cycle = None;
+                }
+                continue;
+            }
+            Err(error) => error,
+        };
+        let backed_up = index > 0
+            && steps[index - 1].kind == StepKind::Backup
+            && steps[index - 1].pair_index == step.pair_index;
+        let (failed_steps, error) = match cycle.take() {
+            Some((start, end)) => (
+                start..=end,
+                undo_steps(file_system, &steps[start..index], error),
+            ),
+            // The backup gets the name of the target back, so the target is not lost
+            None if backed_up => (
+                index - 1..=index,
+                undo_steps(file_system, &steps[index - 1..index], error),
+            ),
+            None => (index..=index, error),
+        };
+        if !move_options.keep_going && *failed_steps.start() == 0 {
+            return Err(error);
+        }
+        if !move_options.keep_going {
+            let done_reports = reports
+                .into_iter()
+                .zip(last_steps)
+                .filter(|(_, last_step)| {
+                    last_step.is_none_or(|last_step| last_step < *failed_steps.start())
+                })
+                .map(|(report, _)| report)
+                .collect();
+            return Err(MmvError::Interrupted {
+                reports: done_reports,
+                source: Box::new(error),
+            });
+        }
+        let error = Arc::new(error);
+        for failed_step in &steps[failed_steps] {
+            reports[failed_step.pair_index].status = MoveStatus::Failed(error.clone());
+        }
+    }
+    Ok(reports)
+}
+
+/// Undoes the done steps (of a cycle or a backup) in reverse order after the error.
+/// If it is not possible, the error tells where the file moved by the first step is left
+fn undo_steps(file_system: &dyn FileSystem, done_steps: &[MoveStep], error: MmvError) -> MmvError {
+    for step in done_steps.iter().rev() {
+        let undone = file_system.rename_no_replace(&step.target_filename, &step.initial_filename);
+        if undone.is_err() {
+            return MmvError::Stranded {
+                initial_filename: done_steps[0].initial_filename.clone(),
+                temporary_filename: done_steps[0].target_filename.clone(),
+                source: Box::new(error),
+            };
+        }
+        log(Verbosity::Verbose, || {
+            format!(
+                "File '{}' is moved back to '{}'",
+                step.target_filename.display(),
+                step.initial_filename.display()
+            )
+        });
+    }
+    error
+}
+
+/// Does one step of the plan. Move fails if the target exists, Replace overwrites it
+fn run_step(file_system: &dyn FileSystem, step: &MoveStep) -> Result<(), MmvError> {
+    let result = match step.kind {
+        StepKind::Move => {
+            file_system.rename_no_replace(&step.initial_filename, &step.target_filename)
+        }
+        StepKind::Replace | StepKind::Backup => {
+            file_system.rename(&step.initial_filename, &step.target_filename)
+        }
+        StepKind::Exchange => file_system.exchange(&step.initial_filename, &step.target_filename),
+    };
+    result.map_err(|error| {
+        MmvError::from_move(
+            file_system,
+            &step.initial_filename,
+            &step.target_filename,
+            error,
+        )
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::files_operations::{get_files_by_template, mass_move, MassMoveError, NoFilesError};
-    use std::fs::{create_dir, create_dir_all, remove_dir_all, File};
-    use std::path::Path;
-    pub static ROOT_DIRECTORY_NAME: &str = "dehftcbt4yu3h53r5435ergieruh";
+    use crate::backups::BackupStyle;
+    use crate::errors::MmvError;
+    use crate::file_system::{MemoryFileSystem, StdFileSystem};
+    use crate::files_operations::{
+        exclude_files, get_files_by_template, get_files_by_template_in, mass_move, mass_move_in,
+        order_moves, read_exclude_patterns, read_files_list, read_moves_map, ConflictPolicy,
+        ExcludeError, MapError, MoveOptions, MoveReport, MoveStatus,
+    };
+    use glob::MatchOptions;
+    use std::fs::{create_dir_all, read_to_string, write, File};
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
     #[test]
     fn test_get_files_by_template1() {
-        let _ = remove_dir_all(ROOT_DIRECTORY_NAME);
-        local_setup_environment();
-        let root = ROOT_DIRECTORY_NAME.to_string();
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
         let mut path1 = root.clone();
         path1.push_str("/path/to/some_*_filename.*");
-        let result1: Result<Vec<String>, NoFilesError> = Ok(vec![
-            (root.clone() + "/path/to/some_A_filename.txt").to_string(),
-            (root.clone() + "/path/to/some_B_filename.jpg").to_string(),
-            (root.clone() + "/path/to/some__filename.gif").to_string(),
-            (root.clone() + "/path/to/some_jnskfjnes_filename.c").to_string(),
+        let result1: Result<Vec<PathBuf>, MmvError> = Ok(vec![
+            PathBuf::from(root.clone() + "/path/to/some_A_filename.txt"),
+            PathBuf::from(root.clone() + "/path/to/some_B_filename.jpg"),
+            PathBuf::from(root.clone() + "/path/to/some__filename.gif"),
+            PathBuf::from(root.clone() + "/path/to/some_jnskfjnes_filename.c"),
         ]);
-        assert_eq!(get_files_by_template(&path1), result1);
-        local_destroy_environment();
+        assert_eq!(get_files_by_template(&path1, MatchOptions::new()), result1);
     }
     #[test]
     fn test_get_files_by_template2() {
-        local_destroy_environment();
-        local_setup_environment();
-        let root = ROOT_DIRECTORY_NAME.to_string();
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
         let mut path2 = root.clone();
         path2.push_str("/Documents/music/*/* - *.mp3");
-        let result2: Result<Vec<String>, NoFilesError> = Ok(vec![
-            (root.clone() + "/Documents/music/pop/ - Maroon5.mp3").to_string(),
-            (root.clone() + "/Documents/music/pop/Neizvesten  - Bez nazvania.mp3").to_string(),
-            (root.clone() + "/Documents/music/rock/A - B.mp3").to_string(),
-            (root.clone() + "/Documents/music/rock/B - D.mp3").to_string(),
-            (root.clone() + "/Documents/music/vk/Izvesten - S nazvaniem.mp3").to_string(),
-            (root.clone() + "/Documents/music/vk/Neizvesten - Bez nazvania.mp3").to_string(),
+        let result2: Result<Vec<PathBuf>, MmvError> = Ok(vec![
+            PathBuf::from(root.clone() + "/Documents/music/pop/ - Maroon5.mp3"),
+            PathBuf::from(root.clone() + "/Documents/music/pop/Neizvesten  - Bez nazvania.mp3"),
+            PathBuf::from(root.clone() + "/Documents/music/rock/A - B.mp3"),
+            PathBuf::from(root.clone() + "/Documents/music/rock/B - D.mp3"),
+            PathBuf::from(root.clone() + "/Documents/music/vk/Izvesten - S nazvaniem.mp3"),
+            PathBuf::from(root.clone() + "/Documents/music/vk/Neizvesten - Bez nazvania.mp3"),
         ]);
-        assert_eq!(get_files_by_template(&path2), result2);
-        local_destroy_environment();
+        assert_eq!(get_files_by_template(&path2, MatchOptions::new()), result2);
     }
 
     #[test]
     fn test_get_files_by_template3() {
-        local_destroy_environment();
-        local_setup_environment();
-        let root = ROOT_DIRECTORY_NAME.to_string();
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
         let mut path3 = root.clone();
         path3.push_str("/Documents/music/* - *.mp3");
-        let result3: Result<Vec<String>, NoFilesError> = Ok(vec![
-            (root.clone() + "/Documents/music/ - Bez nazvania.mp3").to_string(),
-            (root.clone() + "/Documents/music/Neizvesten - Bez nazvania.mp3").to_string(),
+        let result3: Result<Vec<PathBuf>, MmvError> = Ok(vec![
+            PathBuf::from(root.clone() + "/Documents/music/ - Bez nazvania.mp3"),
+            PathBuf::from(root.clone() + "/Documents/music/Neizvesten - Bez nazvania.mp3"),
+        ]);
+        assert_eq!(get_files_by_template(&path3, MatchOptions::new()), result3);
+    }
+
+    #[test]
+    fn test_get_files_by_template_ignore_case() {
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
+        let path = root.clone() + "/path/to/SOME_*_FileName.*";
+        assert_eq!(
+            get_files_by_template(&path, MatchOptions::new()),
+            Err(MmvError::NoFiles(path.clone()))
+        );
+        let ignore_case = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        let result: Result<Vec<PathBuf>, MmvError> = Ok(vec![
+            PathBuf::from(root.clone() + "/path/to/some_A_filename.txt"),
+            PathBuf::from(root.clone() + "/path/to/some_B_filename.jpg"),
+            PathBuf::from(root.clone() + "/path/to/some__filename.gif"),
+            PathBuf::from(root.clone() + "/path/to/some_jnskfjnes_filename.c"),
         ]);
-        assert_eq!(get_files_by_template(&path3), result3);
-        local_destroy_environment();
+        assert_eq!(get_files_by_template(&path, ignore_case), result);
+    }
+
+    #[test]
+    fn test_get_files_by_template_invalid_pattern() {
+        let error = get_files_by_template("photos/***.jpg", MatchOptions::new()).unwrap_err();
+        assert!(matches!(
+            error,
+            MmvError::InvalidPattern { ref pattern, .. } if pattern == "photos/***.jpg"
+        ));
     }
+    #[test]
+    fn test_get_files_by_template_hidden() {
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
+        let _ = File::create(root.clone() + "/path/to/.gitkeep");
+        let _ = File::create(root.clone() + "/path/to/.env.local");
+        let path = root.clone() + "/path/to/*";
+        let no_hidden = MatchOptions {
+            require_literal_leading_dot: true,
+            ..MatchOptions::new()
+        };
+        let all_files = get_files_by_template(&path, MatchOptions::new()).unwrap();
+        let visible_files = get_files_by_template(&path, no_hidden).unwrap();
+        assert!(all_files.contains(&PathBuf::from(root.clone() + "/path/to/.gitkeep")));
+        assert!(all_files.contains(&PathBuf::from(root.clone() + "/path/to/.env.local")));
+        assert_eq!(all_files.len(), 6);
+        assert_eq!(
+            visible_files,
+            vec![
+                PathBuf::from(root.clone() + "/path/to/some_A_filename.txt"),
+                PathBuf::from(root.clone() + "/path/to/some_B_filename.jpg"),
+                PathBuf::from(root.clone() + "/path/to/some__filename.gif"),
+                PathBuf::from(root.clone() + "/path/to/some_jnskfjnes_filename.c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_files_list() {
+        let newline_separated = "./a b.txt\nsub/c.txt\n\n".as_bytes();
+        assert_eq!(
+            read_files_list(newline_separated, false).unwrap(),
+            ["./a b.txt", "sub/c.txt"].map(PathBuf::from)
+        );
+        let nul_separated = "line\nbreak.txt\0d.txt\0".as_bytes();
+        assert_eq!(
+            read_files_list(nul_separated, true).unwrap(),
+            ["line\nbreak.txt", "d.txt"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_exclude_files() {
+        let filenames: Vec<PathBuf> = vec![
+            "reports/report_1.pdf".into(),
+            "reports/report_1_final.pdf".into(),
+            "reports/report_2_FINAL.doc".into(),
+            "reports/.report_3.pdf".into(),
+        ];
+        assert_eq!(
+            exclude_files(
+                filenames.clone(),
+                &["*_final.*".to_string()],
+                MatchOptions::new()
+            ),
+            Ok(vec![
+                "reports/report_1.pdf".into(),
+                "reports/report_2_FINAL.doc".into(),
+                "reports/.report_3.pdf".into(),
+            ])
+        );
+        let ignore_case = MatchOptions {
+            case_sensitive: false,
+            require_literal_leading_dot: true,
+            ..MatchOptions::new()
+        };
+        assert_eq!(
+            exclude_files(
+                filenames.clone(),
+                &["reports/*_final.*".to_string(), "*.pdf".to_string()],
+                ignore_case
+            ),
+            Ok(vec![])
+        );
+        assert_eq!(
+            exclude_files(filenames, &["report_[".to_string()], MatchOptions::new()),
+            Err(ExcludeError::InvalidPattern("report_[".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_read_exclude_patterns() {
+        let directory = local_setup_environment();
+        let exclude_file = root_of(&directory) + "/exclude.txt";
+        let _ = write(&exclude_file, "# drafts\n*_draft.*\n\n  *.tmp  \n");
+        assert_eq!(
+            read_exclude_patterns(&exclude_file),
+            Ok(vec!["*_draft.*".to_string(), "*.tmp".to_string()])
+        );
+        let missing_file = root_of(&directory) + "/missing.txt";
+        assert_eq!(
+            read_exclude_patterns(&missing_file),
+            Err(ExcludeError::ReadError(missing_file.clone()))
+        );
+    }
+
     #[test]
     fn test_mass_move1() {
-        local_destroy_environment();
-        local_setup_environment();
-        let root = ROOT_DIRECTORY_NAME.to_string();
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
         let initial_filenames = vec![
-            (root.clone() + "/path/to/some_A_filename.txt").to_string(),
-            (root.clone() + "/path/to/some_B_filename.jpg").to_string(),
-            (root.clone() + "/path/to/some__filename.gif").to_string(),
-            (root.clone() + "/path/to/some_jnskfjnes_filename.c").to_string(),
+            PathBuf::from(root.clone() + "/path/to/some_A_filename.txt"),
+            PathBuf::from(root.clone() + "/path/to/some_B_filename.jpg"),
+            PathBuf::from(root.clone() + "/path/to/some__filename.gif"),
+            PathBuf::from(root.clone() + "/path/to/some_jnskfjnes_filename.c"),
         ];
         let target_filenames = vec![
-            (root.clone() + "/path/to/changed_A_filename.txt").to_string(),
-            (root.clone() + "/path/to/changed_B_filename.jpg").to_string(),
-            (root.clone() + "/path/to/changed__filename.gif").to_string(),
-            (root.clone() + "/path/to/changed_jnskfjnes_filename.c").to_string(),
+            PathBuf::from(root.clone() + "/path/to/changed_A_filename.txt"),
+            PathBuf::from(root.clone() + "/path/to/changed_B_filename.jpg"),
+            PathBuf::from(root.clone() + "/path/to/changed__filename.gif"),
+            PathBuf::from(root.clone() + "/path/to/changed_jnskfjnes_filename.c"),
         ];
 
         assert_eq!(
-            mass_move(&initial_filenames, &target_filenames, false),
-            Ok(())
+            mass_move(
+                &initial_filenames,
+                &target_filenames,
+                &policy(ConflictPolicy::Fail)
+            ),
+            Ok(reports_with_status(
+                &initial_filenames,
+                &target_filenames,
+                MoveStatus::Moved
+            ))
         );
         for initial_filename in initial_filenames {
             assert!(!Path::new(&initial_filename).exists())
@@ -150,40 +1213,568 @@ mod tests {
         for target_filename in target_filenames {
             assert!(Path::new(&target_filename).exists())
         }
-        local_destroy_environment();
     }
 
     #[test]
     fn test_mass_move2() {
-        local_destroy_environment();
-        local_setup_environment();
-        let root = ROOT_DIRECTORY_NAME.to_string();
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
         let initial_filenames = vec![
-            (root.clone() + "/path/to/changed_A_filename.txt").to_string(),
-            (root.clone() + "/path/to/changed_B_filename.jpg").to_string(),
-            (root.clone() + "/path/to/changed__filename.gif").to_string(),
-            (root.clone() + "/path/to/changed_jnskfjnes_filename.c").to_string(),
+            PathBuf::from(root.clone() + "/path/to/changed_A_filename.txt"),
+            PathBuf::from(root.clone() + "/path/to/changed_B_filename.jpg"),
+            PathBuf::from(root.clone() + "/path/to/changed__filename.gif"),
+            PathBuf::from(root.clone() + "/path/to/changed_jnskfjnes_filename.c"),
         ];
         for initial_filename in &initial_filenames {
-            let _ = File::create(&initial_filename);
+            let _ = File::create(initial_filename);
         }
         assert_eq!(
-            mass_move(&initial_filenames, &initial_filenames, false),
-            Err(MassMoveError::ReplaceError(
-                (root.clone() + "/path/to/changed_A_filename.txt").to_string()
+            mass_move(
+                &initial_filenames,
+                &initial_filenames,
+                &policy(ConflictPolicy::Fail)
+            ),
+            Err(MmvError::TargetExists(
+                (root.clone() + "/path/to/changed_A_filename.txt").into()
+            ))
+        );
+
+        assert_eq!(
+            mass_move(
+                &initial_filenames,
+                &initial_filenames,
+                &policy(ConflictPolicy::Overwrite)
+            ),
+            Ok(reports_with_status(
+                &initial_filenames,
+                &initial_filenames,
+                MoveStatus::Skipped
+            ))
+        );
+    }
+
+    #[test]
+    fn test_read_moves_map() {
+        let directory = local_setup_environment();
+        let csv_file = root_of(&directory) + "/renames.csv";
+        let _ = write(
+            &csv_file,
+            "old,new\r\na.txt,b.txt\r\n# comment\r\n\"c, d.txt\",\"say \"\"hi\"\".txt\"\r\n",
+        );
+        assert_eq!(
+            read_moves_map(&csv_file),
+            Ok((
+                vec!["a.txt".into(), "c, d.txt".into()],
+                vec!["b.txt".into(), "say \"hi\".txt".into()]
+            ))
+        );
+        let tsv_file = root_of(&directory) + "/renames.tsv";
+        let _ = write(&tsv_file, "a,1.txt\tb,1.txt\n");
+        assert_eq!(
+            read_moves_map(&tsv_file),
+            Ok((vec!["a,1.txt".into()], vec!["b,1.txt".into()]))
+        );
+        let _ = write(&csv_file, "a.txt,b.txt\nc.txt\n");
+        assert_eq!(
+            read_moves_map(&csv_file),
+            Err(MapError::FormatError {
+                map_file: csv_file.clone(),
+                line: 2
+            })
+        );
+        #[cfg(unix)]
+        {
+            use crate::os_strings::name_from_bytes;
+            // CP1251 'Фото' and Latin-1 'é' are taken byte for byte
+            let _ = write(&csv_file, b"\xd4\xee\xf2\xee.jpg,caf\xe9.jpg\n");
+            let name = |bytes: &[u8]| PathBuf::from(name_from_bytes(bytes.to_vec()));
+            assert_eq!(
+                read_moves_map(&csv_file),
+                Ok((
+                    vec![name(b"\xd4\xee\xf2\xee.jpg")],
+                    vec![name(b"caf\xe9.jpg")]
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn test_mass_move_in_memory() {
+        let file_system = MemoryFileSystem::new();
+        for name in ["a.txt", "b.txt", "c.txt", "d.txt", "d (1).txt"] {
+            file_system.add_file(format!("root/{}", name), name);
+        }
+        let sources =
+            get_files_by_template_in(&file_system, "root/[abc].txt", MatchOptions::new(), None)
+                .unwrap();
+        assert_eq!(
+            sources,
+            ["root/a.txt", "root/b.txt", "root/c.txt"].map(PathBuf::from)
+        );
+        // 'a' and 'b' are swapped through a temporary name, 'c' goes next to the existing 'd'
+        let targets: Vec<PathBuf> = vec![
+            "root/b.txt".into(),
+            "root/a.txt".into(),
+            "root/d.txt".into(),
+        ];
+        let rename_with_suffix = policy(ConflictPolicy::RenameWithSuffix);
+        let reports = mass_move_in(&file_system, &sources, &targets, &rename_with_suffix).unwrap();
+        assert_eq!(
+            reports[2].status,
+            MoveStatus::Renamed("root/d (2).txt".into())
+        );
+        assert_eq!(
+            file_system.read_file("root/a.txt"),
+            Some("b.txt".to_string())
+        );
+        assert_eq!(
+            file_system.read_file("root/b.txt"),
+            Some("a.txt".to_string())
+        );
+        assert_eq!(
+            file_system.read_file("root/d (2).txt"),
+            Some("c.txt".to_string())
+        );
+
+        file_system.fail_on("root/a.txt", ErrorKind::PermissionDenied);
+        let keep_going = MoveOptions {
+            keep_going: true,
+            ..MoveOptions::default()
+        };
+        let reports = mass_move_in(
+            &file_system,
+            &["root/a.txt", "root/b.txt"],
+            &["root/e.txt", "root/f.txt"],
+            &keep_going,
+        )
+        .unwrap();
+        assert!(matches!(
+            &reports[0].status,
+            MoveStatus::Failed(error) if matches!(**error, MmvError::PermissionDenied { .. })
+        ));
+        assert_eq!(reports[1].status, MoveStatus::Moved);
+        assert_eq!(
+            file_system.files(),
+            [
+                "root/a.txt",
+                "root/d (1).txt",
+                "root/d (2).txt",
+                "root/d.txt",
+                "root/f.txt"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_mass_move_confirmed_overwrites() {
+        let file_system = MemoryFileSystem::new();
+        for name in ["a", "b", "old_a", "old_b"] {
+            file_system.add_file(format!("root/{}", name), name);
+        }
+        let sources = ["root/a", "root/b"];
+        let targets = ["root/old_a", "root/old_b"];
+        // Only the target of the first pair is agreed to be replaced
+        let confirmed = MoveOptions {
+            confirmed_overwrites: vec![true],
+            ..MoveOptions::default()
+        };
+        assert_eq!(
+            mass_move_in(&file_system, &sources, &targets, &confirmed),
+            Err(MmvError::TargetExists("root/old_b".into()))
+        );
+        let confirmed = MoveOptions {
+            confirmed_overwrites: vec![true, false],
+            keep_going: true,
+            ..MoveOptions::default()
+        };
+        let reports = mass_move_in(&file_system, &sources, &targets, &confirmed).unwrap();
+        assert_eq!(reports[0].status, MoveStatus::Overwritten);
+        assert!(matches!(reports[1].status, MoveStatus::Failed(_)));
+        assert_eq!(file_system.read_file("root/old_a"), Some("a".to_string()));
+        assert_eq!(
+            file_system.read_file("root/old_b"),
+            Some("old_b".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mass_move_cycle_undone() {
+        let sources = ["root/a.txt", "root/b.txt", "root/x.txt"];
+        let targets = ["root/b.txt", "root/a.txt", "root/y.txt"];
+        let broken_file_system = || {
+            let file_system = MemoryFileSystem::new();
+            for source in sources {
+                file_system.add_file(source, source);
+            }
+            // 'a' goes to a temporary name, then 'b' can not be moved to its place
+            file_system.fail_on("root/b.txt", ErrorKind::PermissionDenied);
+            file_system
+        };
+        let file_system = broken_file_system();
+        let error = mass_move_in(&file_system, &sources, &targets, &MoveOptions::default());
+        let Err(MmvError::Interrupted { reports, source }) = error else {
+            panic!("cycle must stop mass_move, got {:?}", error);
+        };
+        assert!(matches!(*source, MmvError::PermissionDenied { .. }));
+        assert_eq!(
+            reports,
+            reports_with_status(&["root/x.txt"], &["root/y.txt"], MoveStatus::Moved)
+        );
+        assert_eq!(
+            file_system.files(),
+            ["root/a.txt", "root/b.txt", "root/y.txt"].map(PathBuf::from)
+        );
+        assert_eq!(
+            file_system.read_file("root/a.txt"),
+            Some("root/a.txt".to_string())
+        );
+
+        let file_system = broken_file_system();
+        let keep_going = MoveOptions {
+            keep_going: true,
+            ..MoveOptions::default()
+        };
+        let reports = mass_move_in(&file_system, &sources, &targets, &keep_going).unwrap();
+        assert!(matches!(reports[0].status, MoveStatus::Failed(_)));
+        assert!(matches!(reports[1].status, MoveStatus::Failed(_)));
+        assert_eq!(reports[2].status, MoveStatus::Moved);
+        assert_eq!(
+            file_system.files(),
+            ["root/a.txt", "root/b.txt", "root/y.txt"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_mass_move_backup_restored() {
+        let broken_file_system = || {
+            let file_system = MemoryFileSystem::new();
+            file_system.add_file("root/new.txt", "new");
+            file_system.add_file("root/old.txt", "old");
+            file_system.add_file("root/x.txt", "x");
+            // The target is backed up first, then the source can not be moved to its place
+            file_system.fail_on("root/new.txt", ErrorKind::PermissionDenied);
+            file_system
+        };
+        let sources = ["root/new.txt", "root/x.txt"];
+        let targets = ["root/old.txt", "root/y.txt"];
+        let backup = policy(ConflictPolicy::Backup);
+        let file_system = broken_file_system();
+        let error = mass_move_in(&file_system, &sources, &targets, &backup);
+        assert!(matches!(error, Err(MmvError::PermissionDenied { .. })));
+        assert_eq!(
+            file_system.files(),
+            ["root/new.txt", "root/old.txt", "root/x.txt"].map(PathBuf::from)
+        );
+        assert_eq!(
+            file_system.read_file("root/old.txt"),
+            Some("old".to_string())
+        );
+
+        let file_system = broken_file_system();
+        let keep_going = MoveOptions {
+            keep_going: true,
+            ..backup
+        };
+        let reports = mass_move_in(&file_system, &sources, &targets, &keep_going).unwrap();
+        assert!(matches!(reports[0].status, MoveStatus::Failed(_)));
+        assert_eq!(reports[1].status, MoveStatus::Moved);
+        assert_eq!(
+            file_system.files(),
+            ["root/new.txt", "root/old.txt", "root/y.txt"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_order_moves() {
+        let pair = |initial: &str, target: &str| (PathBuf::from(initial), PathBuf::from(target));
+        let chain = order_moves(
+            &StdFileSystem,
+            &["a", "b"].map(PathBuf::from),
+            &["b", "c"].map(PathBuf::from),
+        );
+        assert_eq!(chain, vec![pair("b", "c"), pair("a", "b")]);
+        let cycle = order_moves(
+            &StdFileSystem,
+            &["a", "b", "c"].map(PathBuf::from),
+            &["b", "c", "a"].map(PathBuf::from),
+        );
+        assert_eq!(
+            cycle,
+            vec![
+                pair("a", "a.mmv-tmp1"),
+                pair("c", "a"),
+                pair("b", "c"),
+                pair("a.mmv-tmp1", "b")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mass_move_cycle_and_collision() {
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
+        let first = root.clone() + "/path/to/some_A_filename.txt";
+        let second = root.clone() + "/path/to/some_B_filename.jpg";
+        let _ = write(&first, "first");
+        let _ = write(&second, "second");
+        assert_eq!(
+            mass_move(
+                &[first.clone(), second.clone()],
+                &[second.clone(), first.clone()],
+                &policy(ConflictPolicy::Fail)
+            ),
+            Ok(reports_with_status(
+                &[first.clone(), second.clone()],
+                &[second.clone(), first.clone()],
+                MoveStatus::Moved
             ))
         );
+        assert_eq!(read_to_string(&first).unwrap(), "second");
+        assert_eq!(read_to_string(&second).unwrap(), "first");
 
+        let target = root.clone() + "/path/to/same.txt";
         assert_eq!(
-            mass_move(&initial_filenames, &initial_filenames, true),
-            Ok(())
+            mass_move(
+                &[first.clone(), second.clone()],
+                &[target.clone(), target.clone()],
+                &policy(ConflictPolicy::Overwrite)
+            ),
+            Err(MmvError::Collision(target.clone().into()))
         );
+        assert!(Path::new(&first).exists());
+        assert!(!Path::new(&target).exists());
+
+        let missing = root.clone() + "/path/to/missing.txt";
+        assert!(matches!(
+            mass_move(
+                std::slice::from_ref(&missing),
+                std::slice::from_ref(&target),
+                &policy(ConflictPolicy::Fail)
+            ),
+            Err(MmvError::SourceVanished { initial_filename, .. }) if initial_filename == missing
+        ));
+    }
 
-        local_destroy_environment();
+    #[test]
+    fn test_mass_move_conflict_policies() {
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
+        let source = root.clone() + "/path/to/some_A_filename.txt";
+        let target = root.clone() + "/path/to/some_B_filename.jpg";
+        let _ = write(&source, "new");
+        let _ = write(&target, "old");
+        let sources = vec![source.clone()];
+        let targets = vec![target.clone()];
+        let pair = |status: MoveStatus| {
+            Ok(vec![MoveReport {
+                initial_filename: source.clone().into(),
+                target_filename: target.clone().into(),
+                status,
+            }])
+        };
+        assert_eq!(
+            mass_move(&sources, &targets, &policy(ConflictPolicy::Skip)),
+            pair(MoveStatus::Skipped)
+        );
+        let target_file = File::options().write(true).open(&target).unwrap();
+        let _ = target_file.set_modified(SystemTime::now() + Duration::from_secs(60));
+        assert_eq!(
+            mass_move(
+                &sources,
+                &targets,
+                &policy(ConflictPolicy::OverwriteIfOlder)
+            ),
+            pair(MoveStatus::Skipped)
+        );
+        let _ = target_file.set_modified(SystemTime::now() - Duration::from_secs(60));
+        assert_eq!(
+            mass_move(
+                &sources,
+                &targets,
+                &policy(ConflictPolicy::OverwriteIfOlder)
+            ),
+            pair(MoveStatus::Overwritten)
+        );
+        assert_eq!(read_to_string(&target).unwrap(), "new");
+
+        let _ = write(&source, "newer");
+        let renamed = root.clone() + "/path/to/some_B_filename (1).jpg";
+        assert_eq!(
+            mass_move(
+                &sources,
+                &targets,
+                &policy(ConflictPolicy::RenameWithSuffix)
+            ),
+            pair(MoveStatus::Renamed(renamed.clone().into()))
+        );
+        assert_eq!(read_to_string(&renamed).unwrap(), "newer");
+
+        let backup = target.clone() + "~";
+        let renamed_sources = vec![renamed.clone()];
+        assert_eq!(
+            mass_move(&renamed_sources, &targets, &policy(ConflictPolicy::Backup)),
+            Ok(vec![MoveReport {
+                initial_filename: renamed.clone().into(),
+                target_filename: target.clone().into(),
+                status: MoveStatus::BackedUp(backup.clone().into()),
+            }])
+        );
+        assert_eq!(read_to_string(&target).unwrap(), "newer");
+        assert_eq!(read_to_string(&backup).unwrap(), "new");
+        assert!(!Path::new(&renamed).exists());
+    }
+
+    #[test]
+    fn test_mass_move_numbered_backups() {
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
+        let target = root.clone() + "/path/to/target.txt";
+        let targets = vec![target.clone()];
+        let move_options = MoveOptions {
+            conflict_policy: ConflictPolicy::Overwrite,
+            backup_style: Some(BackupStyle::Numbered),
+            ..MoveOptions::default()
+        };
+        let _ = write(&target, "0");
+        for version in ["1", "2"] {
+            let source = root.clone() + "/path/to/source.txt";
+            let _ = write(&source, version);
+            assert_eq!(
+                mass_move(std::slice::from_ref(&source), &targets, &move_options),
+                Ok(vec![MoveReport {
+                    initial_filename: source.into(),
+                    target_filename: target.clone().into(),
+                    status: MoveStatus::BackedUp((target.clone() + ".~" + version + "~").into()),
+                }])
+            );
+        }
+        assert_eq!(read_to_string(&target).unwrap(), "2");
+        assert_eq!(read_to_string(target.clone() + ".~1~").unwrap(), "0");
+        assert_eq!(read_to_string(target.clone() + ".~2~").unwrap(), "1");
+    }
+
+    #[test]
+    fn test_mass_move_swap() {
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
+        let staging = root.clone() + "/path/to/some_A_filename.txt";
+        let live = root.clone() + "/path/to/some_B_filename.jpg";
+        let new_staging = root.clone() + "/path/to/some__filename.gif";
+        let new_live = root.clone() + "/path/to/new.gif";
+        let _ = write(&staging, "staging");
+        let _ = write(&live, "live");
+        let swap = MoveOptions {
+            swap: true,
+            ..MoveOptions::default()
+        };
+        assert_eq!(
+            mass_move(
+                &[staging.clone(), new_staging.clone()],
+                &[live.clone(), new_live.clone()],
+                &swap
+            ),
+            Ok(vec![
+                MoveReport {
+                    initial_filename: staging.clone().into(),
+                    target_filename: live.clone().into(),
+                    status: MoveStatus::Swapped,
+                },
+                MoveReport {
+                    initial_filename: new_staging.clone().into(),
+                    target_filename: new_live.clone().into(),
+                    status: MoveStatus::Moved,
+                }
+            ])
+        );
+        assert_eq!(read_to_string(&live).unwrap(), "staging");
+        assert_eq!(read_to_string(&staging).unwrap(), "live");
+        assert!(Path::new(&new_live).exists() && !Path::new(&new_staging).exists());
+        assert_eq!(
+            mass_move(
+                &[staging.clone(), live.clone()],
+                &[live.clone(), new_live.clone()],
+                &swap
+            ),
+            Err(MmvError::Collision(live.clone().into()))
+        );
+        assert_eq!(read_to_string(&live).unwrap(), "staging");
+    }
+
+    #[test]
+    fn test_mass_move_keep_going() {
+        let directory = local_setup_environment();
+        let root = root_of(&directory);
+        let name = |filename: &str| root.clone() + "/path/to/" + filename;
+        let initial_filenames = vec![
+            name("some_A_filename.txt"),
+            name("missing.txt"),
+            name("some_B_filename.jpg"),
+            name("some__filename.gif"),
+            name("some_jnskfjnes_filename.c"),
+        ];
+        let target_filenames = vec![
+            name("A.txt"),
+            name("missing_moved.txt"),
+            name("some__filename.gif"),
+            name("same.c"),
+            name("same.c"),
+        ];
+        let _ = write(name("some__filename.gif"), "kept");
+        let keep_going = MoveOptions {
+            keep_going: true,
+            ..MoveOptions::default()
+        };
+        let reports = mass_move(&initial_filenames, &target_filenames, &keep_going).unwrap();
+        let statuses: Vec<MoveStatus> = reports.into_iter().map(|report| report.status).collect();
+        assert_eq!(statuses[0], MoveStatus::Moved);
+        assert!(matches!(
+            &statuses[1],
+            MoveStatus::Failed(error) if matches!(**error, MmvError::SourceVanished { .. })
+        ));
+        assert_eq!(
+            statuses[2],
+            MoveStatus::Failed(Arc::new(MmvError::TargetExists(
+                name("some__filename.gif").into()
+            )))
+        );
+        for status in &statuses[3..] {
+            assert_eq!(
+                *status,
+                MoveStatus::Failed(Arc::new(MmvError::Collision(name("same.c").into())))
+            );
+        }
+        assert!(Path::new(&name("A.txt")).exists());
+        assert!(Path::new(&name("some_B_filename.jpg")).exists());
+        assert_eq!(read_to_string(name("some__filename.gif")).unwrap(), "kept");
+        assert!(!Path::new(&name("same.c")).exists());
+    }
+
+    fn policy(conflict_policy: ConflictPolicy) -> MoveOptions {
+        MoveOptions {
+            conflict_policy,
+            ..MoveOptions::default()
+        }
+    }
+
+    fn reports_with_status<P: AsRef<Path>>(
+        initial_filenames: &[P],
+        target_filenames: &[P],
+        status: MoveStatus,
+    ) -> Vec<MoveReport> {
+        initial_filenames
+            .iter()
+            .zip(target_filenames)
+            .map(|(initial_filename, target_filename)| MoveReport {
+                initial_filename: initial_filename.as_ref().to_path_buf(),
+                target_filename: target_filename.as_ref().to_path_buf(),
+                status: status.clone(),
+            })
+            .collect()
     }
 
-    fn local_setup_environment() {
-        let _ = create_dir(ROOT_DIRECTORY_NAME);
+    fn local_setup_environment() -> TempDir {
+        let directory = tempfile::tempdir().unwrap();
         let files_directory1: Vec<String> = vec![
             "/path/to/some_A_filename.txt".to_string(),
             "/path/to/some_B_filename.jpg".to_string(),
@@ -203,16 +1794,17 @@ mod tests {
             "/Documents/music/vk/vk/vk/vk/vk/ -  .mp3".to_string(),
         ];
         for filename in &files_directory1 {
-            let mut full_path_string = ROOT_DIRECTORY_NAME.to_string().clone();
-            full_path_string.push_str(&filename);
+            let mut full_path_string = root_of(&directory);
+            full_path_string.push_str(filename);
             let full_path = Path::new(&full_path_string);
             let path_prefix = full_path.parent().unwrap();
             let _ = create_dir_all(path_prefix);
-            let _ = File::create(&full_path);
+            let _ = File::create(full_path);
         }
+        directory
     }
 
-    fn local_destroy_environment() {
-        let _ = remove_dir_all(ROOT_DIRECTORY_NAME);
+    fn root_of(directory: &TempDir) -> String {
+        directory.path().to_str().unwrap().to_string()
     }
 }
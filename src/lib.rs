@@ -0,0 +1,51 @@
+//! Moving files by templates, as the `mmv` utility does
+//!
+//! [`Template`] finds the files by the choice template and builds their new names as a [`Plan`],
+//! [`Executor`] checks the conflicts and moves the files of the plan with the given [`MoveOptions`].
+//! Files are found and moved through a [`FileSystem`]: [`StdFileSystem`] by default, or
+//! [`MemoryFileSystem`] to try the moves (and their failures) without touching real files.
+//! Names are [`PathBuf`](std::path::PathBuf)s, so names that are not valid UTF-8 are moved unchanged.
+//!
+//! ## Example
+//! ```
+//! use mmv::{Executor, MoveOptions, MoveStatus, Template};
+//!
+//! let directory = std::env::temp_dir().join("mmv_library_example");
+//! let _ = std::fs::remove_dir_all(&directory);
+//! std::fs::create_dir_all(&directory).unwrap();
+//! std::fs::write(directory.join("IMG_0001.jpeg"), "").unwrap();
+//! let directory = directory.to_string_lossy();
+//!
+//! let template = Template::new(
+//!     &format!("{}/IMG_*.jpeg", directory),
+//!     &format!("{}/photo_#1.jpg", directory),
+//! );
+//! let plan = template.plan(&template.find_files().unwrap()).unwrap();
+//! let reports = Executor::new(MoveOptions::default()).execute(&plan).unwrap();
+//! assert_eq!(reports[0].target_filename, format!("{}/photo_0001.jpg", directory));
+//! assert_eq!(reports[0].status, MoveStatus::Moved);
+//! # std::fs::remove_dir_all(&*directory).unwrap();
+//! ```
+pub mod backups;
+pub mod confirmation;
+pub mod diff_view;
+pub mod editing;
+pub mod errors;
+pub mod file_system;
+pub mod files_filtering;
+pub mod files_operations;
+pub mod normalization;
+pub mod os_strings;
+pub mod output;
+pub mod plans;
+pub mod renaming;
+pub mod scripts;
+pub mod template_handling;
+pub mod verbosity;
+
+pub use errors::MmvError;
+pub use file_system::{FileSystem, MemoryFileSystem, StdFileSystem};
+pub use files_operations::{
+    ConflictPolicy, Executor, MoveOptions, MovePlan, MoveReport, MoveStatus, Plan,
+};
+pub use template_handling::{Template, TemplateError};
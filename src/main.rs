@@ -1,56 +1,561 @@
-pub mod files_operations;
-pub mod template_handling;
-use crate::template_handling::{MoveBuilder, ParsedTarget};
-use clap::Parser;
-use files_operations::{get_files_by_template, mass_move};
+use clap::{Args, Parser, Subcommand};
+use glob::MatchOptions;
+use mmv::backups::{BackupControl, BackupStyle};
+use mmv::confirmation::confirm_moves_on_terminal;
+use mmv::diff_view::PairStyle;
+use mmv::editing::edit_files_pairs;
+use mmv::errors::{ExitCode, MmvError};
+use mmv::files_filtering::{filter_files, parse_age, parse_size, FileFilter, FileType};
+use mmv::files_operations::{
+    exclude_files, read_exclude_patterns, read_files_list, read_moves_map, ConflictPolicy,
+    Executor, MoveOptions, MoveReport, MoveStatus, Plan,
+};
+use mmv::normalization::NormalForm;
+use mmv::output::{
+    count_statuses, error_record, plan_records, report_records, result_records, write_records,
+    OutputFormat,
+};
+use mmv::plans::SavedPlan;
+use mmv::scripts::{write_shell_script, ScriptFormat};
+use mmv::template_handling::Template;
+use mmv::verbosity::{log, set_verbosity, verbosity, Verbosity};
+use std::ffi::{OsStr, OsString};
+use std::io::{stdin, stdout, Write};
+use std::path::PathBuf;
 use std::process::exit;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
+#[clap(
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    after_help = "Exit codes:
+  0  all the files are moved
+  1  no files suit the choice template
+  2  wrong command line arguments
+  3  wrong template, pattern, map file or edited names
+  4  target files exist or several files go to the same name, nothing is moved
+  5  some of the files are not moved (with '--keep-going')
+  6  file system error
+  7  files have changed since the plan was made (with 'apply')"
+)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    arguments: Arguments,
+    /// Will print nothing but errors (e.g. for cron jobs)
+    #[clap(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Will also print the matched files and the sequences captured from every file to the standard
+    /// error. Given twice ('-vv'), will print every rename call too
+    #[clap(long, short, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Finds the files and builds their new names as usual, but saves the plan to file instead of moving.
+    /// Every file is fingerprinted (inode, size, modification time) to check it at 'apply'
+    Plan {
+        #[clap(flatten)]
+        arguments: Box<Arguments>,
+        /// File to save the plan to
+        #[clap(long, value_name = "FILE")]
+        save: String,
+    },
+    /// Moves the files by the plan saved with 'plan --save', if none of them has changed since then
+    Apply {
+        /// File with the saved plan
+        plan_file: String,
+        /// Format of the report: text, json or jsonl
+        #[clap(long, value_name = "FORMAT", default_value = "text")]
+        output: OutputFormat,
+        /// Will highlight only the changed parts of the names in the text report
+        #[clap(long)]
+        diff: bool,
+    },
+}
+
+#[derive(Args, Debug)]
 struct Arguments {
-    /// Choice files template. Asterisk '*' stands for any sequence of symbols in file name (not in directories)
-    files_template: String,
-    /// Target files template. Inserting '#n', where n is a number 1-9 means you want the sequence under n-th asteriks be placed here
-    target_template: String,
-    /// Will overwrite the target files if they are present in the directory
-    #[clap(long, short)]
+    /// Choice files template. Asterisk '*' stands for any sequence of symbols in file name (not in directories),
+    /// '?' for one symbol, '[...]' for one of the listed symbols and '**' for any number of directories
+    #[clap(required_unless_present = "map")]
+    files_template: Option<String>,
+    /// Target files template. Inserting '#n', where n is a number 1-9 means you want the sequence under n-th wildcard be placed here.
+    /// '#{n|translit}' places the sequence written in ASCII letters ('Ёлка' -> 'Iolka')
+    #[clap(required_unless_present_any = ["map", "edit"])]
+    target_template: Option<OsString>,
+    /// Will overwrite the target files if they are present in the directory (same as '--on-conflict overwrite')
+    #[clap(long, short, conflicts_with = "on_conflict")]
     force: bool,
+    /// What to do if the target file exists: fail (nothing is moved), skip, overwrite,
+    /// overwrite-if-older, rename (to 'name (1).ext') or backup (keep old target as 'name~')
+    #[clap(long, value_name = "POLICY", default_value = "fail")]
+    on_conflict: ConflictPolicy,
+    /// Will keep every overwritten file as a backup. CONTROL is one of: simple ('name' + suffix),
+    /// numbered ('name.~1~'), existing (numbered if numbered backups exist, simple otherwise), none
+    #[clap(
+        long,
+        short = 'b',
+        value_name = "CONTROL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "existing"
+    )]
+    backup: Option<BackupControl>,
+    /// Will exchange every file with its target atomically if the target exists (e.g. to put staging
+    /// files in place of live ones), so neither name is missing at any moment. Needs
+    /// renameat2(RENAME_EXCHANGE) (Linux), the pair fails where the file system does not support it
+    #[clap(long, conflicts_with_all = ["force", "on_conflict", "backup"])]
+    swap: bool,
+    /// Format of the report: text (lines 'old -> new'), json (array of records) or jsonl
+    /// (one record per line). Records are plan, result, error and summary
+    #[clap(long, value_name = "FORMAT", default_value = "text")]
+    output: OutputFormat,
+    /// Will print the plan as a script (only 'sh' is supported) instead of moving the files.
+    /// Cycles are broken by moves through temporary names, as mmv does
+    #[clap(long, value_name = "FORMAT", conflicts_with = "output")]
+    emit_script: Option<ScriptFormat>,
+    /// Will highlight only the changed parts of the names in the text report: common parts are dimmed,
+    /// removed text is red and inserted text is green. Without colors (NO_COLOR is set or the output
+    /// is not a terminal) the changes are shown as 'common{old -> new}common'
+    #[clap(long)]
+    diff: bool,
+    /// Will try to move every file even if some of them fail, then print a summary of succeeded, skipped
+    /// and failed files
+    #[clap(long)]
+    keep_going: bool,
+    /// Suffix of simple backups
+    #[clap(long, short = 'S', value_name = "SUFFIX", default_value = "~")]
+    suffix: String,
+    /// Will match the choice template regardless of letter case ('*.jpg' also takes '.JPG' and '.Jpg' files)
+    #[clap(long, short)]
+    ignore_case: bool,
+    /// Will match hidden files and directories (names starting with '.') too. This is the default
+    #[clap(long, overrides_with = "no_hidden")]
+    hidden: bool,
+    /// Will skip hidden files and directories unless they are written literally in the choice template
+    #[clap(long, overrides_with = "hidden")]
+    no_hidden: bool,
+    /// Will compare the choice template with the file names in the given Unicode normal form: nfc or nfd
+    /// (e.g. 'nfc' to find names that came from macOS in NFD by a template typed in NFC).
+    /// The files are moved by their real names
+    #[clap(long, value_name = "FORM")]
+    normalize: Option<NormalForm>,
+    /// Will also bring the new names to the normal form given with '--normalize'
+    #[clap(long, requires = "normalize")]
+    normalize_targets: bool,
+    /// Glob pattern of files to leave untouched, checked against the path and the file name. Can be repeated
+    #[clap(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// File with exclude patterns, one per line. Empty lines and lines starting with '#' are skipped
+    #[clap(long, value_name = "FILE")]
+    exclude_from: Vec<String>,
+    /// Will take only entries of given types: 'f' (file), 'd' (directory), 'l' (symlink). Several types are separated by comma
+    #[clap(long = "type", value_name = "TYPE", value_delimiter = ',')]
+    file_types: Vec<FileType>,
+    /// Will take only entries of at least this size in bytes. Suffixes K, M, G, T are allowed (e.g. 10K)
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    min_size: Option<u64>,
+    /// Will take only entries of at most this size in bytes. Suffixes K, M, G, T are allowed (e.g. 10M)
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    max_size: Option<u64>,
+    /// Will take only entries modified within given time: number with suffix s, m, h, d or w (e.g. 12h)
+    #[clap(long, value_name = "AGE", value_parser = parse_age)]
+    newer_than: Option<Duration>,
+    /// Will take only entries modified earlier than given time ago: number with suffix s, m, h, d or w (e.g. 30d)
+    #[clap(long, value_name = "AGE", value_parser = parse_age)]
+    older_than: Option<Duration>,
+    /// Will take files to move from standard input (one per line) instead of searching by the choice template.
+    /// The choice template is still used to extract the sequences under the wildcards
+    #[clap(long)]
+    from_stdin: bool,
+    /// Files names in standard input are separated by NUL instead of newline (as 'find -print0' gives)
+    #[clap(short = '0', long = "null", requires = "from_stdin")]
+    nul_separated: bool,
+    /// CSV or TSV file with explicit pairs of old and new names to move instead of using templates
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["files_template", "target_template", "from_stdin"]
+    )]
+    map: Option<String>,
+    /// Will open the new names in $VISUAL or $EDITOR to correct them by hand before moving.
+    /// Without target template the new names start equal to the old ones
+    #[clap(long)]
+    edit: bool,
+    /// Will ask on the terminal before moving every file: yes, no, all (the rest without questions) or quit.
+    /// Existing target files are overwritten only after a separate confirmation
+    #[clap(long)]
+    interactive: bool,
 }
 
 fn main() {
-    let arguments = Arguments::parse();
-    let files_by_template = get_files_by_template(&arguments.files_template);
-    match files_by_template {
-        Err(error_template) => {
-            eprintln!("{}", error_template);
-            exit(1);
+    let cli = Cli::parse();
+    set_verbosity(Verbosity::from_flags(cli.quiet, cli.verbose));
+    match cli.command {
+        None => {
+            let arguments = cli.arguments;
+            let (plan, executor) = prepare_moves(&arguments);
+            if let Some(ScriptFormat::Sh) = arguments.emit_script {
+                match executor.dry_run(&plan) {
+                    Ok(move_plan) => {
+                        let _ = stdout().write_all(&write_shell_script(&move_plan));
+                        exit(ExitCode::Success as i32);
+                    }
+                    Err(plan_error) => fail(plan_error, arguments.output),
+                }
+            }
+            run_moves(
+                &plan,
+                &executor,
+                arguments.output,
+                pair_style(arguments.diff),
+            )
+        }
+        Some(Command::Plan { arguments, save }) => save_plan(&arguments, &save),
+        Some(Command::Apply {
+            plan_file,
+            output,
+            diff,
+        }) => apply_plan(&plan_file, output, pair_style(diff)),
+    }
+}
+
+fn pair_style(diff: bool) -> PairStyle {
+    match diff {
+        true => PairStyle::diff_for_stdout(),
+        false => PairStyle::Plain,
+    }
+}
+
+/// Finds the files to move with their new names (by templates or map file, corrected in
+/// the editor and confirmed if asked) and makes the executor with options from the arguments
+fn prepare_moves(arguments: &Arguments) -> (Plan, Executor) {
+    let (initial_filenames, target_filenames) = match (
+        &arguments.map,
+        &arguments.files_template,
+        &arguments.target_template,
+    ) {
+        (Some(map_file), _, _) => match read_moves_map(map_file) {
+            Err(map_error) => fail(map_error, arguments.output),
+            Ok(files_pairs) => files_pairs,
         },
-        Ok(filenames) => {
-            let move_builder = MoveBuilder::new(&arguments.files_template, &filenames);
-            let parsed_target = ParsedTarget::new(&arguments.target_template);
-            let files_pairs = move_builder.build_target_names(&parsed_target);
-            match files_pairs {
-                Err(template_error)  => {
-                    eprintln!("{}", template_error);
-                    exit(1);
+        (None, Some(files_template), target_template) => {
+            files_pairs_by_templates(arguments, files_template, target_template.as_deref())
+        }
+        _ => unreachable!("clap requires the choice template when there is no map file"),
+    };
+    let (initial_filenames, target_filenames) = match arguments.edit {
+        false => (initial_filenames, target_filenames),
+        true => match edit_files_pairs(&initial_filenames, &target_filenames) {
+            Err(edit_error) => fail(edit_error, arguments.output),
+            Ok(files_pairs) => files_pairs,
+        },
+    };
+    let conflict_policy = match arguments.force {
+        true => ConflictPolicy::Overwrite,
+        false => arguments.on_conflict,
+    };
+    let (initial_filenames, target_filenames, confirmed_overwrites) = match arguments.interactive {
+        false => (initial_filenames, target_filenames, vec![]),
+        true => {
+            let ask_overwrite = conflict_policy == ConflictPolicy::Fail && !arguments.swap;
+            match confirm_moves_on_terminal(&initial_filenames, &target_filenames, ask_overwrite) {
+                Err(terminal_error) => fail(MmvError::Terminal(terminal_error), arguments.output),
+                Ok(confirmed) => (
+                    confirmed.initial_filenames,
+                    confirmed.target_filenames,
+                    confirmed.overwrite,
+                ),
+            }
+        }
+    };
+    let move_options = MoveOptions {
+        conflict_policy,
+        backup_style: match (arguments.backup, conflict_policy) {
+            (Some(backup_control), _) => BackupStyle::new(backup_control, &arguments.suffix),
+            (None, ConflictPolicy::Backup) => {
+                BackupStyle::new(BackupControl::Simple, &arguments.suffix)
+            }
+            (None, _) => None,
+        },
+        swap: arguments.swap,
+        keep_going: arguments.keep_going,
+        confirmed_overwrites,
+    };
+    (
+        Plan::new(initial_filenames, target_filenames),
+        Executor::new(move_options),
+    )
+}
+
+/// Saves the plan to file without moving anything. Conflicts are checked as the executor does
+fn save_plan(arguments: &Arguments, plan_file: &str) -> ! {
+    let (plan, executor) = prepare_moves(arguments);
+    if let Err(plan_error) = executor.dry_run(&plan) {
+        fail(plan_error, arguments.output)
+    }
+    let saved_plan = SavedPlan::new(
+        &plan.initial_filenames,
+        &plan.target_filenames,
+        executor.options(),
+    )
+    .and_then(|saved_plan| saved_plan.save(plan_file).map(|_| saved_plan));
+    if let Err(plan_error) = saved_plan {
+        fail(plan_error, arguments.output)
+    }
+    match arguments.output {
+        OutputFormat::Text if verbosity() == Verbosity::Quiet => {}
+        OutputFormat::Text => {
+            for (initial_filename, target_filename) in plan.pairs() {
+                println!(
+                    "{}",
+                    pair_style(arguments.diff).show(
+                        &initial_filename.to_string_lossy(),
+                        &target_filename.to_string_lossy(),
+                        "->"
+                    )
+                );
+            }
+            println!("mmv: Plan is saved to '{}'", plan_file);
+        }
+        output_format => {
+            let records = plan_records(&plan.initial_filenames, &plan.target_filenames);
+            let _ = write_records(&records, output_format, &mut stdout());
+        }
+    }
+    exit(ExitCode::Success as i32)
+}
+
+/// Moves the files by the saved plan after checking that none of them has changed
+fn apply_plan(plan_file: &str, output_format: OutputFormat, pair_style: PairStyle) -> ! {
+    let saved_plan = match SavedPlan::load(plan_file) {
+        Err(plan_error) => fail(plan_error, output_format),
+        Ok(saved_plan) => saved_plan,
+    };
+    if let Err(plan_error) = saved_plan.verify() {
+        fail(plan_error, output_format)
+    }
+    let (initial_filenames, target_filenames) = saved_plan.files_pairs();
+    run_moves(
+        &Plan::new(initial_filenames, target_filenames),
+        &Executor::new(saved_plan.options),
+        output_format,
+        pair_style,
+    )
+}
+
+/// Moves the files, reports the results in the given format and exits
+fn run_moves(
+    plan: &Plan,
+    executor: &Executor,
+    output_format: OutputFormat,
+    pair_style: PairStyle,
+) -> ! {
+    // JSON lines are printed as soon as they are known, JSON array is printed at the end
+    let mut records = plan_records(&plan.initial_filenames, &plan.target_filenames);
+    if output_format == OutputFormat::Jsonl {
+        let _ = write_records(&records, output_format, &mut stdout());
+        records.clear();
+    }
+    match executor.execute(plan) {
+        Ok(reports) => {
+            let exit_code = match reports
+                .iter()
+                .any(|report| matches!(report.status, MoveStatus::Failed(_)))
+            {
+                true => ExitCode::PartialFailure,
+                false => ExitCode::Success,
+            };
+            if output_format == OutputFormat::Text {
+                print_reports(&reports, pair_style);
+                if verbosity() > Verbosity::Quiet {
+                    if executor.options().keep_going {
+                        print_summary(&reports);
+                    }
+                    if exit_code == ExitCode::Success {
+                        println!("mmv: Succeded!");
+                    }
                 }
-                Ok((initial_filenames, target_filenames)) => {
-                    let resilt_mass_move =
-                        mass_move(&initial_filenames, &target_filenames, arguments.force);
-                    match resilt_mass_move {
-                        Ok(_) => {
-                            for i in 0..initial_filenames.len() {
-                                println!("{} -> {}", &initial_filenames[i], &target_filenames[i]);
-                            }
-                            println!("mmv: Succeded!");
-                            exit(0);
-                        },
-                        Err(replace_error) => {
-                            eprintln!("{}", replace_error);
-                            exit(1);
-                        },
+            } else {
+                for report in &reports {
+                    if let MoveStatus::Failed(move_error) = &report.status {
+                        eprintln!("{}", move_error);
                     }
                 }
+                records.extend(result_records(&reports, exit_code));
+                let _ = write_records(&records, output_format, &mut stdout());
+            }
+            exit(exit_code as i32);
+        }
+        Err(move_error) => {
+            let exit_code = move_error.exit_code();
+            // Files moved before the error are reported, so it is known what is left to do
+            if let MmvError::Interrupted { reports, .. } = &move_error {
+                match output_format {
+                    OutputFormat::Text => print_reports(reports, pair_style),
+                    _ => records.extend(report_records(reports)),
+                }
+            }
+            eprintln!("{}", move_error);
+            records.push(error_record(&move_error, exit_code));
+            let _ = write_records(&records, output_format, &mut stdout());
+            exit(exit_code as i32)
+        }
+    }
+}
+
+/// Prints the error message to the standard error (and as JSON record for JSON output)
+/// and exits with the code of the error
+fn fail(error: impl Into<MmvError>, output_format: OutputFormat) -> ! {
+    let error = error.into();
+    let exit_code = error.exit_code();
+    eprintln!("{}", error);
+    let _ = write_records(
+        &[error_record(&error, exit_code)],
+        output_format,
+        &mut stdout(),
+    );
+    exit(exit_code as i32)
+}
+
+/// Prints what happened to every pair, errors of failed pairs go to the standard error.
+/// Only the errors are printed in quiet mode
+fn print_reports(reports: &[MoveReport], pair_style: PairStyle) {
+    for report in reports {
+        if verbosity() == Verbosity::Quiet {
+            if let MoveStatus::Failed(move_error) = &report.status {
+                eprintln!("{}", move_error);
             }
+            continue;
         }
+        let initial_filename = &report.initial_filename.to_string_lossy();
+        let target_filename = &report.target_filename.to_string_lossy();
+        let pair = pair_style.show(initial_filename, target_filename, "->");
+        match &report.status {
+            MoveStatus::Moved => println!("{}", pair),
+            MoveStatus::Overwritten => println!("{} (overwritten)", pair),
+            MoveStatus::Skipped => println!("{} (skipped, target exists)", pair),
+            MoveStatus::Renamed(new_name) => println!(
+                "{} (target exists, renamed)",
+                pair_style.show(initial_filename, &new_name.to_string_lossy(), "->")
+            ),
+            MoveStatus::BackedUp(backup_name) => {
+                println!("{} (overwritten, backup '{}')", pair, backup_name.display())
+            }
+            MoveStatus::Swapped => println!(
+                "{} (swapped)",
+                pair_style.show(initial_filename, target_filename, "<->")
+            ),
+            MoveStatus::Failed(move_error) => eprintln!("{}", move_error),
+        }
+    }
+}
+
+/// Prints table with numbers of succeeded, skipped and failed pairs, failed pairs are listed
+fn print_summary(reports: &[MoveReport]) {
+    let (succeeded, skipped, failed) = count_statuses(reports);
+    println!("mmv: Summary");
+    println!("  succeeded  {}", succeeded);
+    println!("  skipped    {}", skipped);
+    println!("  failed     {}", failed);
+    for report in reports
+        .iter()
+        .filter(|report| matches!(report.status, MoveStatus::Failed(_)))
+    {
+        println!(
+            "             {} -> {}",
+            report.initial_filename.display(),
+            report.target_filename.display()
+        );
+    }
+}
+
+/// Finds files by the choice template and builds their new names by the target template
+/// (or leaves the names as they are if there is no target template).
+/// Exits with error message if there are no suitable files or templates do not agree
+fn files_pairs_by_templates(
+    arguments: &Arguments,
+    files_template: &str,
+    target_template: Option<&OsStr>,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let match_options = MatchOptions {
+        case_sensitive: !arguments.ignore_case,
+        require_literal_leading_dot: arguments.no_hidden,
+        ..MatchOptions::new()
+    };
+    // Without target template the names are left as they are, so the target does not matter
+    let mut template = Template::new(files_template, target_template.unwrap_or_default())
+        .with_match_options(match_options);
+    if let Some(normal_form) = arguments.normalize {
+        template = template.with_normalization(normal_form, arguments.normalize_targets);
+    }
+    let mut exclude_patterns = arguments.exclude.clone();
+    for exclude_file in &arguments.exclude_from {
+        match read_exclude_patterns(exclude_file) {
+            Err(exclude_error) => fail(exclude_error, arguments.output),
+            Ok(patterns) => exclude_patterns.extend(patterns),
+        }
+    }
+    let filenames = if arguments.from_stdin {
+        match read_files_list(stdin().lock(), arguments.nul_separated) {
+            Err(read_error) => fail(MmvError::FilesList(read_error), arguments.output),
+            Ok(filenames) => filenames
+                .into_iter()
+                .filter(|filename| {
+                    let suits_template = template.matches(filename);
+                    if suits_template {
+                        log(Verbosity::Verbose, || {
+                            format!("File '{}' matches '{}'", filename.display(), files_template)
+                        });
+                    } else {
+                        eprintln!(
+                            "mmv: File '{}' does not suit pattern '{}', skipped",
+                            filename.display(),
+                            files_template
+                        );
+                    }
+                    suits_template
+                })
+                .collect(),
+        }
+    } else {
+        match template.find_files() {
+            Err(files_error) => fail(files_error, arguments.output),
+            Ok(filenames) => filenames,
+        }
+    };
+    let filenames = match exclude_files(filenames, &exclude_patterns, match_options) {
+        Err(exclude_error) => fail(exclude_error, arguments.output),
+        Ok(filenames) => filenames,
+    };
+    let file_filter = FileFilter {
+        file_types: arguments.file_types.clone(),
+        min_size: arguments.min_size,
+        max_size: arguments.max_size,
+        newer_than: arguments.newer_than,
+        older_than: arguments.older_than,
+    };
+    let filenames = filter_files(filenames, &file_filter);
+    if filenames.is_empty() {
+        fail(
+            MmvError::NoFiles(files_template.to_string()),
+            arguments.output,
+        );
+    }
+    if target_template.is_none() {
+        return (filenames.clone(), filenames);
+    }
+    match template.plan(&filenames) {
+        Err(template_error) => fail(template_error, arguments.output),
+        // Every found file may be left out by the plan, e.g. hidden ones
+        Ok(plan) if plan.initial_filenames.is_empty() => fail(
+            MmvError::NoFiles(files_template.to_string()),
+            arguments.output,
+        ),
+        Ok(plan) => (plan.initial_filenames, plan.target_filenames),
     }
 }
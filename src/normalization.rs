@@ -0,0 +1,59 @@
+use std::ffi::{OsStr, OsString};
+use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalForm {
+    /// Unicode normal form names are compared in. The same 'ё' is one code point in NFC
+    /// (as typed on most systems) and two in NFD (as macOS keeps names)
+    Nfc,
+    Nfd,
+}
+
+impl FromStr for NormalForm {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "nfc" => Ok(NormalForm::Nfc),
+            "nfd" => Ok(NormalForm::Nfd),
+            _ => Err(format!(
+                "wrong normal form '{}', expected one of: nfc, nfd",
+                value
+            )),
+        }
+    }
+}
+
+impl NormalForm {
+    pub fn normalize(&self, text: &str) -> String {
+        match self {
+            NormalForm::Nfc => text.nfc().collect(),
+            NormalForm::Nfd => text.nfd().collect(),
+        }
+    }
+
+    /// Names that are not valid UTF-8 are left as they are
+    pub fn normalize_name(&self, name: &OsStr) -> OsString {
+        match name.to_str() {
+            Some(text) => OsString::from(self.normalize(text)),
+            None => name.to_os_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalization::NormalForm;
+
+    #[test]
+    fn test_normalize() {
+        let composed = "\u{451}lka.txt";
+        let decomposed = "\u{435}\u{308}lka.txt";
+        assert_eq!(NormalForm::Nfc.normalize(decomposed), composed);
+        assert_eq!(NormalForm::Nfd.normalize(composed), decomposed);
+        assert_eq!(NormalForm::Nfc.normalize("plain.txt"), "plain.txt");
+        assert_eq!("nfd".parse(), Ok(NormalForm::Nfd));
+        assert!("nfkc".parse::<NormalForm>().is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// Bytes of the name exactly as the system keeps them (names on Unix are not always UTF-8).
+/// On other systems the name is taken as UTF-8 with invalid symbols replaced
+#[cfg(unix)]
+pub fn name_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(name.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn name_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Owned(name.to_string_lossy().into_owned().into_bytes())
+}
+
+/// Name made of the bytes given by name_bytes
+#[cfg(unix)]
+pub fn name_from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+pub fn name_from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Appends the suffix to the last component of the path: 'a.txt' -> 'a.txt.mmv-tmp1'
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Position of the first occurrence of needle in haystack
+pub fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::os_strings::{find_bytes, name_bytes, name_from_bytes, with_suffix};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_os_strings() {
+        assert_eq!(find_bytes(b"some_A_file", b"_file"), Some(6));
+        assert_eq!(find_bytes(b"some", b"x"), None);
+        assert_eq!(find_bytes(b"some", b""), Some(0));
+        assert_eq!(
+            with_suffix(Path::new("dir/a.txt"), ".mmv-tmp1"),
+            PathBuf::from("dir/a.txt.mmv-tmp1")
+        );
+        // Latin-1 'é' is not valid UTF-8, yet it must survive the round trip on Unix
+        #[cfg(unix)]
+        {
+            let name = name_from_bytes(b"caf\xe9.txt".to_vec());
+            assert!(name.to_str().is_none());
+            assert_eq!(&*name_bytes(&name), b"caf\xe9.txt");
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::errors::ExitCode;
+use crate::files_operations::{MoveReport, MoveStatus};
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    /// How mmv reports what it does: lines 'old -> new' for people, or JSON records for programs
+    #[default]
+    Text,
+    /// One JSON array with all the records, printed at the end
+    Json,
+    /// One JSON record per line, printed as soon as it is known
+    Jsonl,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(format!(
+                "wrong output format '{}', expected one of: text, json, jsonl",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    /// Pair that is going to be moved
+    Plan { source: String, target: String },
+    /// What happened to the pair. Status is one of: moved, overwritten, skipped, renamed,
+    /// backed_up, swapped, failed
+    Result {
+        source: String,
+        target: String,
+        status: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        renamed_to: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        backup: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// Error that stopped mmv
+    Error { message: String, exit_code: i32 },
+    /// Last record of successful or partially successful run
+    Summary {
+        succeeded: usize,
+        skipped: usize,
+        failed: usize,
+        exit_code: i32,
+    },
+}
+
+/// Counts succeeded, skipped and failed pairs
+pub fn count_statuses(reports: &[MoveReport]) -> (usize, usize, usize) {
+    let skipped = reports
+        .iter()
+        .filter(|report| report.status == MoveStatus::Skipped)
+        .count();
+    let failed = reports
+        .iter()
+        .filter(|report| matches!(report.status, MoveStatus::Failed(_)))
+        .count();
+    (reports.len() - skipped - failed, skipped, failed)
+}
+
+/// Names that are not valid UTF-8 are shown with U+FFFD in the records, as JSON strings
+/// can not keep them
+pub fn plan_records(initial_filenames: &[PathBuf], target_filenames: &[PathBuf]) -> Vec<Record> {
+    initial_filenames
+        .iter()
+        .zip(target_filenames)
+        .map(|(initial_filename, target_filename)| Record::Plan {
+            source: initial_filename.display().to_string(),
+            target: target_filename.display().to_string(),
+        })
+        .collect()
+}
+
+/// Makes record for every report and the summary record at the end
+pub fn result_records(reports: &[MoveReport], exit_code: ExitCode) -> Vec<Record> {
+    let mut records = report_records(reports);
+    let (succeeded, skipped, failed) = count_statuses(reports);
+    records.push(Record::Summary {
+        succeeded,
+        skipped,
+        failed,
+        exit_code: exit_code as i32,
+    });
+    records
+}
+
+/// Makes record for every report
+pub fn report_records(reports: &[MoveReport]) -> Vec<Record> {
+    reports
+        .iter()
+        .map(|report| {
+            let (status, renamed_to, backup, error) = match &report.status {
+                MoveStatus::Moved => ("moved", None, None, None),
+                MoveStatus::Overwritten => ("overwritten", None, None, None),
+                MoveStatus::Skipped => ("skipped", None, None, None),
+                MoveStatus::Renamed(new_name) => {
+                    ("renamed", Some(new_name.display().to_string()), None, None)
+                }
+                MoveStatus::BackedUp(backup_name) => (
+                    "backed_up",
+                    None,
+                    Some(backup_name.display().to_string()),
+                    None,
+                ),
+                MoveStatus::Swapped => ("swapped", None, None, None),
+                MoveStatus::Failed(move_error) => {
+                    ("failed", None, None, Some(move_error.to_string()))
+                }
+            };
+            Record::Result {
+                source: report.initial_filename.display().to_string(),
+                target: report.target_filename.display().to_string(),
+                status,
+                renamed_to,
+                backup,
+                error,
+            }
+        })
+        .collect()
+}
+
+pub fn error_record(error: &impl Display, exit_code: ExitCode) -> Record {
+    Record::Error {
+        message: error.to_string(),
+        exit_code: exit_code as i32,
+    }
+}
+
+/// Writes records in the given JSON format, nothing is written for OutputFormat::Text
+pub fn write_records<W: Write>(
+    records: &[Record],
+    output_format: OutputFormat,
+    writer: &mut W,
+) -> io::Result<()> {
+    match output_format {
+        OutputFormat::Text => {}
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *writer, records)?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Jsonl => {
+            for record in records {
+                serde_json::to_writer(&mut *writer, record)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::{ExitCode, MmvError};
+    use crate::files_operations::{MoveReport, MoveStatus};
+    use crate::output::{result_records, write_records, OutputFormat};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_write_records() {
+        let reports = vec![
+            MoveReport {
+                initial_filename: "a.txt".into(),
+                target_filename: "b.txt".into(),
+                status: MoveStatus::Renamed("b (1).txt".into()),
+            },
+            MoveReport {
+                initial_filename: "c.txt".into(),
+                target_filename: "d.txt".into(),
+                status: MoveStatus::Failed(Arc::new(MmvError::TargetExists("d.txt".into()))),
+            },
+        ];
+        let mut output: Vec<u8> = vec![];
+        let records = result_records(&reports, ExitCode::PartialFailure);
+        write_records(&records, OutputFormat::Jsonl, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"type\":\"result\",\"source\":\"a.txt\",\"target\":\"b.txt\",\"status\":\"renamed\",\"renamed_to\":\"b (1).txt\"}\n\
+             {\"type\":\"result\",\"source\":\"c.txt\",\"target\":\"d.txt\",\"status\":\"failed\",\"error\":\"mmv: Not able to replace existing file: 'd.txt'\"}\n\
+             {\"type\":\"summary\",\"succeeded\":1,\"skipped\":0,\"failed\":1,\"exit_code\":5}\n"
+        );
+        let mut output: Vec<u8> = vec![];
+        write_records(&records, OutputFormat::Text, &mut output).unwrap();
+        assert!(output.is_empty());
+        assert_eq!("jsonl".parse(), Ok(OutputFormat::Jsonl));
+    }
+}
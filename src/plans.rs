@@ -0,0 +1,295 @@
+use crate::file_system::{FileSystem, StdFileSystem};
+use crate::files_operations::MoveOptions;
+use crate::os_strings::{name_bytes, name_from_bytes};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fs::{read_to_string, write};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use thiserror::Error;
+
+/// Version of the plan file format, plans of other versions are not applied
+pub const PLAN_VERSION: u32 = 1;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PlanError {
+    /// Custom error for plans saved to file and applied later
+    #[error("mmv: Not able to read plan file '{0}'")]
+    ReadError(String),
+    #[error("mmv: Not able to save plan to file '{0}'")]
+    WriteError(String),
+    #[error("mmv: File '{0}' is not a plan saved by this version of mmv")]
+    FormatError(String),
+    #[error("mmv: File '{0}' is missing, nothing is moved")]
+    SourceMissing(String),
+    #[error("mmv: File '{0}' has changed since the plan was made, nothing is moved")]
+    SourceChanged(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// What is checked to tell the file is the same as at planning. Symlinks are not followed
+    pub inode: u64,
+    pub size: u64,
+    pub modified_seconds: u64,
+    pub modified_nanoseconds: u32,
+}
+
+impl Fingerprint {
+    pub fn of(file_system: &dyn FileSystem, filename: &Path) -> io::Result<Self> {
+        let metadata = file_system.symlink_metadata(filename)?;
+        let modified = metadata
+            .modified
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(Fingerprint {
+            inode: metadata.inode,
+            size: metadata.len,
+            modified_seconds: modified.as_secs(),
+            modified_nanoseconds: modified.subsec_nanos(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedMove {
+    #[serde(with = "stored_name")]
+    pub source: PathBuf,
+    #[serde(with = "stored_name")]
+    pub target: PathBuf,
+    pub fingerprint: Fingerprint,
+}
+
+/// Names are stored as JSON strings, and those that are not valid UTF-8
+/// as objects with the raw bytes: {"bytes": [99, 97, 102, 233]}
+mod stored_name {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum StoredName {
+        Text(String),
+        Bytes { bytes: Vec<u8> },
+    }
+
+    pub fn serialize<S: Serializer>(name: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        match name.to_str() {
+            Some(text) => StoredName::Text(text.to_string()),
+            None => StoredName::Bytes {
+                bytes: name_bytes(name.as_os_str()).into_owned(),
+            },
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(match StoredName::deserialize(deserializer)? {
+            StoredName::Text(text) => PathBuf::from(text),
+            StoredName::Bytes { bytes } => PathBuf::from(name_from_bytes(bytes)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedPlan {
+    /// Pairs to move with the options of mass_move, as they were at planning
+    pub version: u32,
+    pub options: MoveOptions,
+    pub moves: Vec<PlannedMove>,
+}
+
+impl SavedPlan {
+    /// Takes fingerprints of all the sources
+    pub fn new(
+        initial_filenames: &[PathBuf],
+        target_filenames: &[PathBuf],
+        move_options: &MoveOptions,
+    ) -> Result<Self, PlanError> {
+        SavedPlan::new_in(
+            &StdFileSystem,
+            initial_filenames,
+            target_filenames,
+            move_options,
+        )
+    }
+
+    /// Takes fingerprints of all the sources in file_system
+    pub fn new_in(
+        file_system: &dyn FileSystem,
+        initial_filenames: &[PathBuf],
+        target_filenames: &[PathBuf],
+        move_options: &MoveOptions,
+    ) -> Result<Self, PlanError> {
+        let moves = initial_filenames
+            .iter()
+            .zip(target_filenames)
+            .map(|(initial_filename, target_filename)| {
+                Ok(PlannedMove {
+                    source: initial_filename.clone(),
+                    target: target_filename.clone(),
+                    fingerprint: Fingerprint::of(file_system, initial_filename).map_err(|_| {
+                        PlanError::SourceMissing(initial_filename.display().to_string())
+                    })?,
+                })
+            })
+            .collect::<Result<Vec<PlannedMove>, PlanError>>()?;
+        Ok(SavedPlan {
+            version: PLAN_VERSION,
+            options: move_options.clone(),
+            moves,
+        })
+    }
+
+    pub fn save(&self, plan_file: &str) -> Result<(), PlanError> {
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|_| PlanError::WriteError(plan_file.to_string()))?;
+        write(plan_file, text + "\n").map_err(|_| PlanError::WriteError(plan_file.to_string()))
+    }
+
+    pub fn load(plan_file: &str) -> Result<Self, PlanError> {
+        let text =
+            read_to_string(plan_file).map_err(|_| PlanError::ReadError(plan_file.to_string()))?;
+        serde_json::from_str::<SavedPlan>(&text)
+            .ok()
+            .filter(|plan| plan.version == PLAN_VERSION)
+            .ok_or_else(|| PlanError::FormatError(plan_file.to_string()))
+    }
+
+    /// Checks that every source is still the same file as at planning
+    pub fn verify(&self) -> Result<(), PlanError> {
+        self.verify_in(&StdFileSystem)
+    }
+
+    /// Checks that every source is still the same file in file_system as at planning
+    pub fn verify_in(&self, file_system: &dyn FileSystem) -> Result<(), PlanError> {
+        for planned_move in &self.moves {
+            match Fingerprint::of(file_system, &planned_move.source) {
+                Err(_) => {
+                    return Err(PlanError::SourceMissing(
+                        planned_move.source.display().to_string(),
+                    ))
+                }
+                Ok(fingerprint) if fingerprint != planned_move.fingerprint => {
+                    return Err(PlanError::SourceChanged(
+                        planned_move.source.display().to_string(),
+                    ))
+                }
+                Ok(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Old and new names of all the moves
+    pub fn files_pairs(&self) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        self.moves
+            .iter()
+            .map(|planned_move| (planned_move.source.clone(), planned_move.target.clone()))
+            .unzip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_system::MemoryFileSystem;
+    use crate::files_operations::{ConflictPolicy, MoveOptions};
+    use crate::plans::{PlanError, PlannedMove, SavedPlan};
+    use std::fs::{remove_file, write};
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_saved_plan() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().to_str().unwrap().to_string();
+        let path = |name: &str| PathBuf::from(root.clone() + name);
+        let initial_filenames = vec![path("/a.txt"), path("/b.txt")];
+        let target_filenames = vec![path("/c.txt"), path("/d.txt")];
+        let plan_file = root.clone() + "/plan.json";
+        let move_options = MoveOptions {
+            conflict_policy: ConflictPolicy::RenameWithSuffix,
+            ..MoveOptions::default()
+        };
+        assert_eq!(
+            SavedPlan::new(&initial_filenames, &target_filenames, &move_options),
+            Err(PlanError::SourceMissing(root.clone() + "/a.txt"))
+        );
+        let _ = write(&initial_filenames[0], "a");
+        let _ = write(&initial_filenames[1], "b");
+        let plan = SavedPlan::new(&initial_filenames, &target_filenames, &move_options).unwrap();
+        assert_eq!(plan.save(&plan_file), Ok(()));
+        let loaded_plan = SavedPlan::load(&plan_file).unwrap();
+        assert_eq!(loaded_plan, plan);
+        assert_eq!(
+            loaded_plan.files_pairs(),
+            (initial_filenames.clone(), target_filenames)
+        );
+        assert_eq!(loaded_plan.verify(), Ok(()));
+
+        let _ = write(&initial_filenames[1], "changed");
+        assert_eq!(
+            loaded_plan.verify(),
+            Err(PlanError::SourceChanged(root.clone() + "/b.txt"))
+        );
+        let _ = remove_file(&initial_filenames[0]);
+        assert_eq!(
+            loaded_plan.verify(),
+            Err(PlanError::SourceMissing(root.clone() + "/a.txt"))
+        );
+        #[cfg(unix)]
+        {
+            use crate::os_strings::name_from_bytes;
+            let latin1_name = PathBuf::from(name_from_bytes(b"caf\xe9.txt".to_vec()));
+            let planned_move = PlannedMove {
+                source: latin1_name.clone(),
+                target: PathBuf::from("cafe.txt"),
+                fingerprint: plan.moves[0].fingerprint,
+            };
+            let text = serde_json::to_string(&planned_move).unwrap();
+            assert!(text.starts_with("{\"source\":{\"bytes\":[99,97,102,233,"));
+            assert_eq!(
+                serde_json::from_str::<PlannedMove>(&text).unwrap(),
+                planned_move
+            );
+        }
+        let _ = write(&plan_file, "{\"version\": 2}");
+        assert_eq!(
+            SavedPlan::load(&plan_file),
+            Err(PlanError::FormatError(plan_file.clone()))
+        );
+    }
+
+    #[test]
+    fn test_saved_plan_in_memory() {
+        let file_system = MemoryFileSystem::new();
+        file_system.add_file("a.txt", "a");
+        let initial_filenames = vec![PathBuf::from("a.txt")];
+        let target_filenames = vec![PathBuf::from("b.txt")];
+        let plan = SavedPlan::new_in(
+            &file_system,
+            &initial_filenames,
+            &target_filenames,
+            &MoveOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(plan.moves[0].fingerprint.size, 1);
+        assert_eq!(plan.verify_in(&file_system), Ok(()));
+        // Same size, but modified later than at planning
+        file_system.set_modified("a.txt", UNIX_EPOCH + Duration::from_secs(60));
+        assert_eq!(
+            plan.verify_in(&file_system),
+            Err(PlanError::SourceChanged("a.txt".to_string()))
+        );
+        file_system.add_file("a.txt", "longer");
+        assert_eq!(
+            plan.verify_in(&file_system),
+            Err(PlanError::SourceChanged("a.txt".to_string()))
+        );
+        file_system.fail_on("a.txt", ErrorKind::NotFound);
+        assert_eq!(
+            plan.verify_in(&file_system),
+            Err(PlanError::SourceMissing("a.txt".to_string()))
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::verbosity::{log, Verbosity};
+use std::fs::{self, symlink_metadata};
+use std::io;
+use std::path::Path;
+
+/// Renames the file with rename(2), replacing the existing target
+pub fn rename(initial_path: &Path, target_path: &Path) -> io::Result<()> {
+    log(Verbosity::Debug, || {
+        format!(
+            "rename('{}', '{}')",
+            initial_path.display(),
+            target_path.display()
+        )
+    });
+    fs::rename(initial_path, target_path)
+}
+
+/// Calls renameat2 with given flags, paths are relative to the current directory
+#[cfg(target_os = "linux")]
+fn renameat2(initial_path: &Path, target_path: &Path, flags: libc::c_uint) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    log(Verbosity::Debug, || {
+        let flags_name = match flags {
+            libc::RENAME_NOREPLACE => "RENAME_NOREPLACE",
+            libc::RENAME_EXCHANGE => "RENAME_EXCHANGE",
+            _ => "0",
+        };
+        format!(
+            "renameat2('{}', '{}', {})",
+            initial_path.display(),
+            target_path.display(),
+            flags_name
+        )
+    });
+    let initial_path = CString::new(initial_path.as_os_str().as_bytes())?;
+    let target_path = CString::new(target_path.as_os_str().as_bytes())?;
+    // Called through syscall, as the wrapper is missing in older C libraries
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            initial_path.as_ptr(),
+            libc::AT_FDCWD,
+            target_path.as_ptr(),
+            flags,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Tells whether renameat2 failed because the kernel or the filesystem does not support the flags
+#[cfg(target_os = "linux")]
+fn is_unsupported(error: &io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP)
+    )
+}
+
+/// Renames the file only if the target name is free, otherwise fails with ErrorKind::AlreadyExists
+///
+/// On Linux the check and the rename are one atomic renameat2(RENAME_NOREPLACE) call, so a target
+/// created by another process in the meantime is never overwritten. Where it is not supported
+/// (other systems, old kernels, some network filesystems) the target is checked right before rename
+pub fn rename_no_replace(initial_path: &Path, target_path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    match renameat2(initial_path, target_path, libc::RENAME_NOREPLACE) {
+        Err(error) if is_unsupported(&error) => {}
+        result => return result,
+    }
+    if symlink_metadata(target_path).is_ok() {
+        return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+    }
+    rename(initial_path, target_path)
+}
+
+/// Exchanges two existing files, so that neither name is missing at any moment
+///
+/// It is one atomic renameat2(RENAME_EXCHANGE) call. Where it is not supported (other systems,
+/// old kernels, some network filesystems) it fails with ErrorKind::Unsupported and both files
+/// are left in place, as exchanging them through a temporary name would not be atomic
+pub fn exchange_files(first_path: &Path, second_path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    match renameat2(first_path, second_path, libc::RENAME_EXCHANGE) {
+        Err(error) if is_unsupported(&error) => {}
+        result => return result,
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "atomic exchange is not supported here (needs renameat2 with RENAME_EXCHANGE on Linux)",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renaming::{exchange_files, rename_no_replace};
+    use std::fs::{read_to_string, write};
+    use std::io;
+    use std::path::Path;
+
+    #[test]
+    fn test_rename_no_replace() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().to_str().unwrap().to_string();
+        let source = root.clone() + "/source.txt";
+        let target = root.clone() + "/target.txt";
+        let _ = write(&source, "source");
+        let _ = write(&target, "target");
+        assert_eq!(
+            rename_no_replace(Path::new(&source), Path::new(&target))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(read_to_string(&source).unwrap(), "source");
+        assert_eq!(read_to_string(&target).unwrap(), "target");
+
+        let free_target = root.clone() + "/free.txt";
+        assert!(rename_no_replace(Path::new(&source), Path::new(&free_target)).is_ok());
+        assert_eq!(read_to_string(&free_target).unwrap(), "source");
+        assert!(!Path::new(&source).exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_exchange_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().to_str().unwrap().to_string();
+        let live = root.clone() + "/config.yml";
+        let staging = root.clone() + "/config.yml.new";
+        let _ = write(&live, "live");
+        let _ = write(&staging, "staging");
+        assert!(exchange_files(Path::new(&staging), Path::new(&live)).is_ok());
+        assert_eq!(read_to_string(&live).unwrap(), "staging");
+        assert_eq!(read_to_string(&staging).unwrap(), "live");
+        let missing = root.clone() + "/missing.yml";
+        assert!(exchange_files(Path::new(&missing), Path::new(&live)).is_err());
+        assert_eq!(read_to_string(&live).unwrap(), "staging");
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn test_exchange_files_unsupported() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().to_str().unwrap().to_string();
+        let live = root.clone() + "/config.yml";
+        let staging = root.clone() + "/config.yml.new";
+        let _ = write(&live, "live");
+        let _ = write(&staging, "staging");
+        let error = exchange_files(Path::new(&staging), Path::new(&live)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(read_to_string(&live).unwrap(), "live");
+        assert_eq!(read_to_string(&staging).unwrap(), "staging");
+    }
+}
@@ -0,0 +1,194 @@
+use crate::file_system::StdFileSystem;
+use crate::files_operations::{temporary_name, MovePlan, MoveStatus, StepKind};
+use crate::os_strings::name_bytes;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptFormat {
+    /// Language of the script with the plan instead of moving the files
+    Sh,
+}
+
+impl FromStr for ScriptFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "sh" => Ok(ScriptFormat::Sh),
+            _ => Err(format!("wrong script format '{}', expected: sh", value)),
+        }
+    }
+}
+
+/// Quotes the name for POSIX shell, so it is taken literally whatever symbols it has.
+/// The name is kept as bytes, so names that are not valid UTF-8 are quoted unchanged
+pub fn shell_quote(name: &OsStr) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for &byte in name_bytes(name).iter() {
+        match byte {
+            b'\'' => quoted.extend_from_slice(b"'\\''"),
+            _ => quoted.push(byte),
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+/// Builds POSIX shell script that does the plan step by step
+///
+/// Every move that must not replace a file is checked first: the script stops if the target
+/// exists, as 'mv -n' on many systems skips it and exits with success, and the next steps
+/// would run against the wrong files. Exchanges are done by three moves through a temporary
+/// name. Skipped and failed pairs are left as comments. The script is bytes, as the names may be not valid UTF-8
+pub fn write_shell_script(plan: &MovePlan) -> Vec<u8> {
+    let mut script = b"#!/bin/sh\n# Generated by mmv, review before running\nset -e\n".to_vec();
+    let mut taken_names: HashSet<PathBuf> = plan
+        .reports
+        .iter()
+        .flat_map(|report| {
+            [
+                report.initial_filename.clone(),
+                report.target_filename.clone(),
+            ]
+        })
+        .collect();
+    for report in &plan.reports {
+        let comment = match &report.status {
+            MoveStatus::Skipped => "skipped, target exists".to_string(),
+            MoveStatus::Failed(move_error) => move_error.to_string(),
+            _ => continue,
+        };
+        // Newline in a name must not end the comment
+        let comment = format!(
+            "{} -> {}: {}",
+            report.initial_filename.display(),
+            report.target_filename.display(),
+            comment
+        );
+        script.extend_from_slice(format!("# {}\n", comment.replace('\n', "\\n")).as_bytes());
+    }
+    let mv = |options: &str, initial_filename: &Path, target_filename: &Path| {
+        let quoted_target = shell_quote(target_filename.as_os_str());
+        let mut command = vec![];
+        if options == "-n " {
+            command.extend_from_slice(
+                [
+                    &b"if [ -e "[..],
+                    &quoted_target,
+                    b" ] || [ -L ",
+                    &quoted_target,
+                    b" ]; then printf \"mmv: Not able to replace existing file: '%s'\\n\" ",
+                    &quoted_target,
+                    b" >&2; exit 1; fi\n",
+                ]
+                .concat()
+                .as_slice(),
+            );
+        }
+        command.extend_from_slice(
+            [
+                format!("mv {}-- ", options).as_bytes(),
+                &shell_quote(initial_filename.as_os_str()),
+                b" ",
+                &quoted_target,
+                b"\n",
+            ]
+            .concat()
+            .as_slice(),
+        );
+        command
+    };
+    for step in &plan.steps {
+        let initial_filename = &step.initial_filename;
+        let target_filename = &step.target_filename;
+        match step.kind {
+            StepKind::Move => script.extend(mv("-n ", initial_filename, target_filename)),
+            StepKind::Replace | StepKind::Backup => {
+                script.extend(mv("-f ", initial_filename, target_filename))
+            }
+            StepKind::Exchange => {
+                let temporary_filename =
+                    temporary_name(&StdFileSystem, initial_filename, &taken_names);
+                taken_names.insert(temporary_filename.clone());
+                script.extend(mv("-n ", initial_filename, &temporary_filename));
+                script.extend(mv("-n ", target_filename, initial_filename));
+                script.extend(mv("-n ", &temporary_filename, target_filename));
+            }
+        }
+    }
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::files_operations::{plan_moves, MoveOptions};
+    use crate::scripts::{shell_quote, write_shell_script};
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_write_shell_script() {
+        assert_eq!(
+            shell_quote(OsStr::new("it's $HOME.txt")),
+            b"'it'\\''s $HOME.txt'"
+        );
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().to_str().unwrap();
+        let first = root.to_string() + "/-a.txt";
+        let second = root.to_string() + "/b c.txt";
+        std::fs::write(&first, "a").unwrap();
+        std::fs::write(&second, "b").unwrap();
+        let plan = plan_moves(
+            &[first.clone(), second.clone()],
+            &[second.clone(), first.clone()],
+            &MoveOptions::default(),
+        )
+        .unwrap();
+        let check = |target: &str| {
+            format!(
+                "if [ -e '{target}' ] || [ -L '{target}' ]; then printf \"mmv: Not able to replace \
+                 existing file: '%s'\\n\" '{target}' >&2; exit 1; fi\n"
+            )
+        };
+        assert_eq!(
+            String::from_utf8(write_shell_script(&plan)).unwrap(),
+            "#!/bin/sh\n# Generated by mmv, review before running\nset -e\n".to_string()
+                + &check(&format!("{root}/-a.txt.mmv-tmp1"))
+                + &format!("mv -n -- '{root}/-a.txt' '{root}/-a.txt.mmv-tmp1'\n")
+                + &check(&format!("{root}/-a.txt"))
+                + &format!("mv -n -- '{root}/b c.txt' '{root}/-a.txt'\n")
+                + &check(&format!("{root}/b c.txt"))
+                + &format!("mv -n -- '{root}/-a.txt.mmv-tmp1' '{root}/b c.txt'\n")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_script_stops_on_existing_target() {
+        let directory = tempfile::tempdir().unwrap();
+        let first = directory.path().join("a.txt");
+        let second = directory.path().join("b.txt");
+        std::fs::write(&first, "a").unwrap();
+        let plan = plan_moves(&[&first], &[&second], &MoveOptions::default()).unwrap();
+        let script = directory.path().join("moves.sh");
+        std::fs::write(&script, write_shell_script(&plan)).unwrap();
+        // The target appears after the plan is made
+        std::fs::write(&second, "b").unwrap();
+        let output = std::process::Command::new("sh")
+            .arg(&script)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            format!(
+                "mmv: Not able to replace existing file: '{}'\n",
+                second.display()
+            )
+        );
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "a");
+        assert_eq!(std::fs::read_to_string(&second).unwrap(), "b");
+    }
+}
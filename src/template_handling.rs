@@ -1,76 +1,183 @@
-use regex::Regex;
-use std::fmt;
-use std::{result::Result, str, usize};
+use crate::errors::MmvError;
+use crate::file_system::{FileSystem, StdFileSystem};
+use crate::files_operations::{get_files_by_template_in, Plan};
+use crate::normalization::NormalForm;
+use crate::os_strings::{find_bytes, name_bytes, name_from_bytes};
+use crate::verbosity::{log, Verbosity};
+use deunicode::deunicode;
+use glob::{MatchOptions, Pattern};
+use regex::bytes::Regex;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::{result::Result, str};
 use thiserror::Error;
 
 #[derive(Debug, Error, PartialEq)]
-pub struct TemplateError {
+pub enum TemplateError {
     /// Custom error handling mistakes in code of this file
-    pub asterisks: usize,
-    pub hashes: usize,
+    #[error(
+        "mmv: In target template there are {hashes} variables,
+                                but in the choice template only {asterisks}"
+    )]
+    NotEnoughAsterisks { asterisks: usize, hashes: usize },
+    #[error("mmv: Unknown filter '{0}' in target template, expected: translit")]
+    UnknownFilter(String),
 }
 
-impl fmt::Display for TemplateError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "mmv: In target template there are {} variables,
-                                but in the choice template only {}",
-            self.hashes, self.asterisks
-        )
+/// Applies the filters of a '#{n|filter}' placeholder to the captured sequence, in the given order
+///
+/// 'translit' writes Cyrillic and other non-Latin scripts in ASCII ('Ёлка' -> 'Iolka').
+/// The sequence is composed (NFC) first, so names kept decomposed by macOS give the same result.
+/// Bytes that are not valid UTF-8 become '?', and '/' that some symbols turn into ('½' -> '1/2')
+/// is replaced with '_', so the sequence stays in one path component
+fn apply_filters(sequence: &OsStr, filters: &[String]) -> Result<OsString, TemplateError> {
+    let mut sequence = sequence.to_os_string();
+    for filter in filters {
+        sequence = match filter.as_str() {
+            "translit" => {
+                let composed = NormalForm::Nfc.normalize(&sequence.to_string_lossy());
+                OsString::from(deunicode(&composed).replace('/', "_"))
+            }
+            _ => return Err(TemplateError::UnknownFilter(filter.clone())),
+        };
     }
+    Ok(sequence)
 }
 
 #[derive(Debug, PartialEq)]
 pub struct MoveBuilder {
     /// Struct that holds pattern of initial choice filenames and allows to create new names with given target templates
     asterisk_number: usize,
-    filenames: Vec<String>,
-    asterisk_sequences: Vec<Vec<String>>,
+    filenames: Vec<PathBuf>,
+    asterisk_sequences: Vec<Vec<OsString>>,
 }
 
 impl MoveBuilder {
     /// Creates new MoveBuilder by template and files corresponding to it
     ///
+    /// Filenames are handled as bytes, so names that are not valid UTF-8 (e.g. Latin-1 names
+    /// of old archives) are captured and put into target names unchanged
+    ///
     /// ## Example
     /// ```
-    /// let move_builder = MoveBuilder::new("/some*file.txt", vec!["/someAfile.txt".to_string(), "/someBfile.txt".to_string()]);
+    /// use mmv::template_handling::MoveBuilder;
+    ///
+    /// let files = vec!["/someAfile.txt", "/someBfile.txt"];
+    /// let move_builder = MoveBuilder::new("/some*file.txt", &files);
     /// ```
-    pub fn new(template: &str, files_to_move: &Vec<String>) -> Self {
-        let mut asterisk_sequences: Vec<Vec<String>> = vec![];
-        let mut filenames: Vec<String> = vec![];
-        let splitted_template: Vec<String> = template
-            .split('*')
-            .map(|substring| substring.to_string())
-            .collect();
-        for filename_string in files_to_move {
-            let mut asterisk_sequence: Vec<String> = vec![];
-            let mut shift: usize = splitted_template[0].len();
-            for i in 1..splitted_template.len() {
-                let Some(current_part_index) = filename_string[shift..].find(&splitted_template[i])
-                else {
-                    break;
-                };
-                if splitted_template[i].is_empty() {
-                    if i == splitted_template.len() - 1 {
-                        asterisk_sequence.push(filename_string[shift..].to_string());
-                    } else {
-                        asterisk_sequence.push("".to_string());
-                    }
-                } else {
-                    let current_asterisk: String =
-                        filename_string[shift..shift + current_part_index].to_string();
-                    shift += current_part_index + splitted_template[i].len();
-                    asterisk_sequence.push(current_asterisk);
+    pub fn new<P: AsRef<Path>>(template: &str, files_to_move: &[P]) -> Self {
+        MoveBuilder::with_options(template, files_to_move, MatchOptions::new())
+    }
+
+    /// Creates new MoveBuilder the same way as 'new', but with the matching rules of match_options
+    ///
+    /// If match_options.case_sensitive is false, stable parts of the template are found in filenames
+    /// regardless of ASCII case (as glob does), but the captured sequences keep the original casing.
+    /// Files that do not match the template under match_options are left out (e.g. '.env.local' for
+    /// template '*.local' when match_options.require_literal_leading_dot is true)
+    ///
+    /// ## Example
+    /// ```
+    /// use glob::MatchOptions;
+    /// use mmv::template_handling::{MoveBuilder, ParsedTarget};
+    /// use std::path::PathBuf;
+    ///
+    /// let options = MatchOptions { case_sensitive: false, ..MatchOptions::new() };
+    /// let move_builder = MoveBuilder::with_options("/some*.jpg", &["/someA.JPG"], options);
+    /// let (_, target_filenames) = move_builder.build_target_names(&ParsedTarget::new("/#1.jpg")).unwrap();
+    /// assert_eq!(target_filenames, [PathBuf::from("/A.jpg")]);
+    /// ```
+    pub fn with_options<P: AsRef<Path>>(
+        template: &str,
+        files_to_move: &[P],
+        match_options: MatchOptions,
+    ) -> Self {
+        MoveBuilder::with_normalization(template, files_to_move, match_options, None)
+    }
+
+    /// Creates new MoveBuilder the same way as 'with_options', but with normal_form the template
+    /// and the filenames are brought to that Unicode normal form before the sequences are captured,
+    /// so the sequences are in that form too. The filenames themselves are kept as they are,
+    /// so the files can still be found by them
+    ///
+    /// ## Example
+    /// ```
+    /// use glob::MatchOptions;
+    /// use mmv::normalization::NormalForm;
+    /// use mmv::template_handling::{MoveBuilder, ParsedTarget};
+    /// use std::path::PathBuf;
+    ///
+    /// // 'ё' is typed composed (NFC), but the file came from macOS decomposed (NFD)
+    /// let files = vec!["/\u{435}\u{308}lka.txt"];
+    /// let nfc = Some(NormalForm::Nfc);
+    /// let move_builder = MoveBuilder::with_normalization("/\u{451}*.txt", &files, MatchOptions::new(), nfc);
+    /// let (initial_filenames, target_filenames) =
+    ///     move_builder.build_target_names(&ParsedTarget::new("/#1.txt")).unwrap();
+    /// assert_eq!(initial_filenames, [PathBuf::from("/\u{435}\u{308}lka.txt")]);
+    /// assert_eq!(target_filenames, [PathBuf::from("/lka.txt")]);
+    /// ```
+    pub fn with_normalization<P: AsRef<Path>>(
+        template: &str,
+        files_to_move: &[P],
+        match_options: MatchOptions,
+        normal_form: Option<NormalForm>,
+    ) -> Self {
+        let template = match normal_form {
+            Some(normal_form) => normal_form.normalize(template),
+            None => template.to_string(),
+        };
+        let template = template.as_str();
+        // An invalid template matches nothing, so it has no wildcards either
+        let pattern = Pattern::new(template)
+            .ok()
+            .and_then(|_| TemplateRegex::new(template, match_options));
+        let asterisk_number = pattern.as_ref().map_or(0, |pattern| {
+            pattern
+                .wildcards
+                .iter()
+                .filter(|&&wildcard| wildcard)
+                .count()
+        });
+        let mut asterisk_sequences: Vec<Vec<OsString>> = vec![];
+        let mut filenames: Vec<PathBuf> = vec![];
+        for filename in files_to_move {
+            let filename = filename.as_ref();
+            let searched_name = match normal_form {
+                Some(normal_form) => {
+                    PathBuf::from(normal_form.normalize_name(filename.as_os_str()))
                 }
-            }
-            filenames.push(filename_string.clone());
+                None => filename.to_path_buf(),
+            };
+            let captures = pattern.as_ref().and_then(|pattern| {
+                pattern.captures(&name_bytes(searched_name.as_os_str()), match_options)
+            });
+            let Some(asterisk_sequence) = captures else {
+                log(Verbosity::Verbose, || {
+                    format!(
+                        "File '{}' does not match '{}', left out",
+                        filename.display(),
+                        template
+                    )
+                });
+                continue;
+            };
+            log(Verbosity::Verbose, || {
+                let captures: Vec<String> = asterisk_sequence
+                    .iter()
+                    .enumerate()
+                    .map(|(index, sequence)| {
+                        format!("#{} = '{}'", index + 1, sequence.to_string_lossy())
+                    })
+                    .collect();
+                format!(
+                    "File '{}' captures {}",
+                    filename.display(),
+                    captures.join(", ")
+                )
+            });
+            filenames.push(filename.to_path_buf());
             asterisk_sequences.push(asterisk_sequence);
         }
-        let asterisk_number = match asterisk_sequences.len() {
-            0 => 0,
-            _ => asterisk_sequences[0].len(),
-        };
         MoveBuilder {
             asterisk_number,
             filenames,
@@ -82,50 +189,62 @@ impl MoveBuilder {
     ///
     /// Puts the substrings that were decoded as those under the '*' in choice template
     /// in the places that ParsedTarget.template_index_sequence tells
-    /// Get the tuple of old names and new names string vectors
+    /// Get the tuple of old names and new names vectors
     ///
     /// ## Example
     /// ```
-    /// // Continue example for 'new'
-    /// let parsed_target = ParsedTarget{
-    ///     stable_filename_parts: vec!["changed".to_string(), "file.jpg".to_string()],
-    ///     template_index_sequence: vec![1]
+    /// use mmv::template_handling::{MoveBuilder, ParsedTarget};
+    /// use std::path::PathBuf;
+    ///
+    /// let files = vec!["/someAfile.txt", "/someBfile.txt"];
+    /// let move_builder = MoveBuilder::new("/some*file.txt", &files);
+    /// let parsed_target = ParsedTarget {
+    ///     stable_filename_parts: vec!["/changed".into(), "file.jpg".into()],
+    ///     template_index_sequence: vec![1],
+    ///     template_filters: vec![vec![]],
     /// };
-    /// let target_names = move_builder.build_target_names(&parsed_target);
-    /// // Second vector of unwrapped target_names is ["changedAfile.jpg", "changedBfile.jpg"]
+    /// let (_, target_filenames) = move_builder.build_target_names(&parsed_target).unwrap();
+    /// assert_eq!(
+    ///     target_filenames,
+    ///     [PathBuf::from("/changedAfile.jpg"), PathBuf::from("/changedBfile.jpg")]
+    /// );
     /// ```
     pub fn build_target_names(
         &self,
         parsed_target_template: &ParsedTarget,
-    ) -> Result<(Vec<String>, Vec<String>), TemplateError> {
+    ) -> Result<(Vec<PathBuf>, Vec<PathBuf>), TemplateError> {
         let max_target_template_some = parsed_target_template.template_index_sequence.iter().max();
         let max_target_template_number: usize = match max_target_template_some.is_some() {
-            true => max_target_template_some.unwrap().clone(),
+            true => *max_target_template_some.unwrap(),
             false => 0,
         };
         if self.asterisk_number < max_target_template_number {
-            Err(TemplateError {
+            Err(TemplateError::NotEnoughAsterisks {
                 asterisks: self.asterisk_number,
                 hashes: max_target_template_number,
             })
         } else {
-            let mut final_target_filenames: Vec<String> = vec![];
+            // Unknown filters are reported even if there are no files
+            for filters in &parsed_target_template.template_filters {
+                apply_filters(OsStr::new(""), filters)?;
+            }
+            let mut final_target_filenames: Vec<PathBuf> = vec![];
             for i in 0..self.filenames.len() {
-                let mut splitted_target_filename: Vec<String> = vec![];
-                splitted_target_filename.resize_with(
-                    2 * parsed_target_template.stable_filename_parts.len() - 1,
-                    || "".to_string(),
-                );
+                let mut target_filename = OsString::new();
                 for j in 0..parsed_target_template.stable_filename_parts.len() {
-                    splitted_target_filename[j * 2] =
-                        parsed_target_template.stable_filename_parts[j].clone();
-                }
-                for j in 0..parsed_target_template.template_index_sequence.len() {
-                    splitted_target_filename[j * 2 + 1] = self.asterisk_sequences[i]
-                        [parsed_target_template.template_index_sequence[j] - 1]
-                        .clone();
+                    target_filename.push(&parsed_target_template.stable_filename_parts[j]);
+                    if let Some(index) = parsed_target_template.template_index_sequence.get(j) {
+                        let filters = parsed_target_template
+                            .template_filters
+                            .get(j)
+                            .map_or(&[][..], Vec::as_slice);
+                        target_filename.push(apply_filters(
+                            &self.asterisk_sequences[i][index - 1],
+                            filters,
+                        )?);
+                    }
                 }
-                final_target_filenames.push(splitted_target_filename.join(""));
+                final_target_filenames.push(PathBuf::from(target_filename));
             }
 
             Ok((self.filenames.clone(), final_target_filenames))
@@ -133,71 +252,367 @@ impl MoveBuilder {
     }
 }
 
+/// Checks whether filename suits the choice template under match_options
+///
+/// The template is matched the way glob matches it: '?' takes one character, '[...]' one of
+/// the listed characters and '**' any number of directories. If
+/// match_options.require_literal_leading_dot is true, a wildcard never takes the '.' that
+/// starts a name, so 'dir/.*' takes 'dir/.gitkeep', but 'dir/*' and '*' do not.
+/// The filename is also matched as bytes, so a name that is not valid UTF-8 is never mistaken
+/// for another one, its invalid bytes can be matched only by wildcards
+pub fn matches_template(template: &str, filename: &Path, match_options: MatchOptions) -> bool {
+    Pattern::new(template).is_ok()
+        && TemplateRegex::new(template, match_options).is_some_and(|pattern| {
+            pattern
+                .captures(&name_bytes(filename.as_os_str()), match_options)
+                .is_some()
+        })
+}
+
+#[derive(Debug)]
+struct TemplateRegex {
+    /// The choice template translated to a regex over the bytes of filenames.
+    /// Every wildcard ('*', '?', '[...]' and '**' that is a whole component) is a capture group,
+    /// and so is every '.' that starts a component of the template, wildcards tells which is which
+    regex: Regex,
+    wildcards: Vec<bool>,
+}
+
+impl TemplateRegex {
+    /// Wildcards take as few characters as they can, so '*' ends at the first occurrence of
+    /// the following stable part. Returns None if the template can not be translated
+    fn new(template: &str, match_options: MatchOptions) -> Option<Self> {
+        let separator = match_options.require_literal_separator;
+        // Valid UTF-8 is taken by whole characters, as glob does, invalid bytes one by one
+        let any_character = match separator {
+            true => "(?:(?u:[^/])|[^/])",
+            false => "(?:(?u:.)|.)",
+        };
+        let characters: Vec<char> = template.chars().collect();
+        let mut pattern = String::new();
+        let mut wildcards: Vec<bool> = vec![];
+        let mut i = 0;
+        while i < characters.len() {
+            let starts_component = i == 0 || characters[i - 1] == '/';
+            match characters[i] {
+                '*' if characters.get(i + 1) == Some(&'*') => {
+                    if !starts_component || !matches!(characters.get(i + 2), None | Some('/')) {
+                        return None;
+                    }
+                    // The '/' after '**' is taken too, so that '**' can stand for no directory
+                    wildcards.push(true);
+                    match characters.get(i + 2) {
+                        Some(_) => pattern.push_str("((?:[^/]*/)*?)"),
+                        None => pattern.push_str("(.*?)"),
+                    }
+                    i += 3;
+                    continue;
+                }
+                '*' if separator => pattern.push_str("([^/]*?)"),
+                '*' => pattern.push_str("(.*?)"),
+                '?' => pattern.push_str(&format!("({})", any_character)),
+                '.' if starts_component => {
+                    pattern.push_str("(\\.)");
+                    wildcards.push(false);
+                    i += 1;
+                    continue;
+                }
+                '[' => {
+                    let negated = characters.get(i + 1) == Some(&'!');
+                    let first = if negated { i + 2 } else { i + 1 };
+                    // The first character is always in the set, even if it is ']'
+                    let end = first
+                        + 1
+                        + characters
+                            .get(first + 1..)?
+                            .iter()
+                            .position(|&c| c == ']')?;
+                    let mut set = String::new();
+                    let mut j = first;
+                    while j < end {
+                        let character = regex::escape(&characters[j].to_string());
+                        if j + 2 < end && characters[j + 1] == '-' {
+                            let last = regex::escape(&characters[j + 2].to_string());
+                            set.push_str(&format!("{}-{}", character, last));
+                            j += 3;
+                        } else {
+                            set.push_str(&character);
+                            j += 1;
+                        }
+                    }
+                    // Invalid bytes are never listed, so they are taken only by '[!...]'
+                    let class = match (negated, separator) {
+                        (false, false) => format!("(?u:[{}])", set),
+                        (false, true) => format!("(?u:[{}&&[^/]])", set),
+                        (true, false) => format!("(?u:[^{}])|[\\x80-\\xFF]", set),
+                        (true, true) => format!("(?u:[^{}/])|[\\x80-\\xFF]", set),
+                    };
+                    pattern.push_str(&format!("({})", class));
+                    wildcards.push(true);
+                    i = end + 1;
+                    continue;
+                }
+                character => {
+                    pattern.push_str(&regex::escape(&character.to_string()));
+                    i += 1;
+                    continue;
+                }
+            }
+            wildcards.push(true);
+            i += 1;
+        }
+        // Without Unicode mode '.' takes any byte and case is folded for ASCII only, as glob does
+        let flags = match match_options.case_sensitive {
+            true => "s-u",
+            false => "si-u",
+        };
+        Some(TemplateRegex {
+            regex: Regex::new(&format!("(?{})^{}$", flags, pattern)).ok()?,
+            wildcards,
+        })
+    }
+
+    /// Captured sequences of the wildcards, or None if the filename does not match.
+    ///
+    /// If match_options.require_literal_leading_dot is true, a name that starts with '.' matches
+    /// only through a '.' that starts a component of the template, so wildcards never take it
+    /// ('dir/.*' takes 'dir/.gitkeep', but 'dir/*' and 'dir/*.*' do not)
+    fn captures(&self, filename: &[u8], match_options: MatchOptions) -> Option<Vec<OsString>> {
+        let captures = self.regex.captures(filename)?;
+        let groups = captures.iter().skip(1).zip(&self.wildcards);
+        if match_options.require_literal_leading_dot {
+            let literal_dots: Vec<usize> = groups
+                .clone()
+                .filter(|(_, &wildcard)| !wildcard)
+                .filter_map(|(group, _)| group.map(|group| group.start()))
+                .collect();
+            let hidden = filename.iter().enumerate().any(|(i, &byte)| {
+                let component = &filename[i..];
+                let component =
+                    &component[..find_bytes(component, b"/").unwrap_or(component.len())];
+                byte == b'.'
+                    && (i == 0 || filename[i - 1] == b'/')
+                    && component != b"."
+                    && component != b".."
+                    && !literal_dots.contains(&i)
+            });
+            if hidden {
+                return None;
+            }
+        }
+        Some(
+            groups
+                .filter(|(_, &wildcard)| wildcard)
+                .map(|(group, _)| {
+                    name_from_bytes(group.map_or(vec![], |group| group.as_bytes().to_vec()))
+                })
+                .collect(),
+        )
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct ParsedTarget {
-    pub stable_filename_parts: Vec<String>,
+    /// Filters of every placeholder go in template_filters, in the same order as the indices
+    pub stable_filename_parts: Vec<OsString>,
     pub template_index_sequence: Vec<usize>,
+    pub template_filters: Vec<Vec<String>>,
 }
 
 impl ParsedTarget {
     /// Takes string and finds all "#n", n is digit 1-9. Splits the string by them - these are stable parts.
+    /// Placeholder may also be written as "#{n}" or with filters "#{n|translit}"
     ///
     /// # Example
     /// ```
-    /// parsed_target = ParsedTarget::new("changed#1file.txt")
-    /// // stable_filename_parts == ["changed", "file.txt"]
-    /// // template_index_sequence == [1]
+    /// use mmv::template_handling::ParsedTarget;
+    ///
+    /// let parsed_target = ParsedTarget::new("changed#1file.txt");
+    /// assert_eq!(parsed_target.stable_filename_parts, ["changed", "file.txt"]);
+    /// assert_eq!(parsed_target.template_index_sequence, [1]);
+    ///
+    /// let parsed_target = ParsedTarget::new("music/#{1|translit}.mp3");
+    /// assert_eq!(parsed_target.stable_filename_parts, ["music/", ".mp3"]);
+    /// assert_eq!(parsed_target.template_filters, [["translit"]]);
     /// ```
-    pub fn new(target_template: &str) -> Self {
-        let hash_regex = Regex::new("#[1-9]").unwrap();
-        let template_index_sequence: Vec<usize> = hash_regex
-            .find_iter(target_template)
-            .filter_map(|pattern| pattern.as_str().parse().ok())
-            .map(|substring: String| {
-                (substring.as_bytes()[1] as char).to_digit(10).unwrap() as usize
-            })
-            .collect();
-        let stable_filename_parts: Vec<String> = hash_regex
-            .replace_all(target_template, "\n")
-            .split('\n')
-            .map(|substring| substring.to_string())
+    pub fn new(target_template: impl AsRef<OsStr>) -> Self {
+        let target_template = name_bytes(target_template.as_ref());
+        let hash_regex = Regex::new(r"#([1-9])|#\{([1-9])((?:\|[^|}]*)*)\}").unwrap();
+        let mut template_index_sequence: Vec<usize> = vec![];
+        let mut template_filters: Vec<Vec<String>> = vec![];
+        for captures in hash_regex.captures_iter(&target_template) {
+            let index = captures.get(1).or_else(|| captures.get(2)).unwrap();
+            template_index_sequence.push((index.as_bytes()[0] - b'0') as usize);
+            let filters = captures
+                .get(3)
+                .map_or(&b""[..], |filters| filters.as_bytes());
+            template_filters.push(
+                String::from_utf8_lossy(filters)
+                    .split('|')
+                    .skip(1)
+                    .map(|filter| filter.trim().to_string())
+                    .collect(),
+            );
+        }
+        let stable_filename_parts: Vec<OsString> = hash_regex
+            .split(&target_template)
+            .map(|substring| name_from_bytes(substring.to_vec()))
             .collect();
         ParsedTarget {
             stable_filename_parts,
             template_index_sequence,
+            template_filters,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    /// Pair of choice and target templates, the entry point of the library
+    choice_template: String,
+    target_template: OsString,
+    match_options: MatchOptions,
+    normal_form: Option<NormalForm>,
+    normalize_targets: bool,
+}
+
+impl Template {
+    /// Takes the templates as they are given on the command line: '*' in the choice template
+    /// stands for any sequence of symbols, '#n' in the target template puts the n-th of them
+    ///
+    /// ## Example
+    /// ```
+    /// use mmv::Template;
+    /// use std::path::PathBuf;
+    ///
+    /// let template = Template::new("photos/IMG_*.jpeg", "photos/#1.jpg");
+    /// let plan = template.plan(&["photos/IMG_0001.jpeg"]).unwrap();
+    /// assert_eq!(plan.target_filenames, [PathBuf::from("photos/0001.jpg")]);
+    /// ```
+    pub fn new(choice_template: &str, target_template: impl AsRef<OsStr>) -> Self {
+        Template {
+            choice_template: choice_template.to_string(),
+            target_template: target_template.as_ref().to_os_string(),
+            match_options: MatchOptions::new(),
+            normal_form: None,
+            normalize_targets: false,
+        }
+    }
+
+    /// Sets the matching rules (letter case, hidden files) for both finding and planning
+    pub fn with_match_options(self, match_options: MatchOptions) -> Self {
+        Template {
+            match_options,
+            ..self
+        }
+    }
+
+    /// Compares the choice template with the names in the given Unicode normal form, both
+    /// when finding and when planning. If normalize_targets is true, the whole new names
+    /// (not only the captured sequences) are brought to that form too
+    ///
+    /// ## Example
+    /// ```
+    /// use mmv::normalization::NormalForm;
+    /// use mmv::Template;
+    /// use std::path::PathBuf;
+    ///
+    /// let template = Template::new("\u{451}*.txt", "\u{435}\u{308}#1.txt")
+    ///     .with_normalization(NormalForm::Nfc, true);
+    /// let plan = template.plan(&["\u{435}\u{308}lka.txt"]).unwrap();
+    /// assert_eq!(plan.target_filenames, [PathBuf::from("\u{451}lka.txt")]);
+    /// ```
+    pub fn with_normalization(self, normal_form: NormalForm, normalize_targets: bool) -> Self {
+        Template {
+            normal_form: Some(normal_form),
+            normalize_targets,
+            ..self
         }
     }
+
+    /// Checks whether the file suits the choice template, the same way plan does
+    pub fn matches(&self, filename: &Path) -> bool {
+        let (template, filename) = match self.normal_form {
+            Some(normal_form) => (
+                normal_form.normalize(&self.choice_template),
+                PathBuf::from(normal_form.normalize_name(filename.as_os_str())),
+            ),
+            None => (self.choice_template.clone(), filename.to_path_buf()),
+        };
+        matches_template(&template, &filename, self.match_options)
+    }
+
+    /// Finds the existing files that suit the choice template
+    pub fn find_files(&self) -> Result<Vec<PathBuf>, MmvError> {
+        self.find_files_in(&StdFileSystem)
+    }
+
+    pub fn find_files_in(&self, file_system: &dyn FileSystem) -> Result<Vec<PathBuf>, MmvError> {
+        get_files_by_template_in(
+            file_system,
+            &self.choice_template,
+            self.match_options,
+            self.normal_form,
+        )
+    }
+
+    /// Builds the new names of the given files. Files that do not suit the choice template are left out
+    pub fn plan<P: AsRef<Path>>(&self, filenames: &[P]) -> Result<Plan, MmvError> {
+        let move_builder = MoveBuilder::with_normalization(
+            &self.choice_template,
+            filenames,
+            self.match_options,
+            self.normal_form,
+        );
+        let (initial_filenames, mut target_filenames) =
+            move_builder.build_target_names(&ParsedTarget::new(&self.target_template))?;
+        if let (Some(normal_form), true) = (self.normal_form, self.normalize_targets) {
+            for target_filename in target_filenames.iter_mut() {
+                *target_filename =
+                    PathBuf::from(normal_form.normalize_name(target_filename.as_os_str()));
+            }
+        }
+        Ok(Plan::new(initial_filenames, target_filenames))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::template_handling::{MoveBuilder, ParsedTarget, TemplateError};
+    use crate::errors::MmvError;
+    use crate::file_system::MemoryFileSystem;
+    use crate::normalization::NormalForm;
+    use crate::template_handling::{
+        matches_template, MoveBuilder, ParsedTarget, Template, TemplateError,
+    };
+    use glob::MatchOptions;
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
     #[test]
     fn test_parsing_template1() {
         let template = "/Desktop/path/to/changed_*_filename.*";
-        let filenames = vec![
-            "/Desktop/path/to/changed_A_filename.txt".to_string(),
-            "/Desktop/path/to/changed_B_filename.jpg".to_string(),
-            "/Desktop/path/to/changed__filename.gif".to_string(),
-            "/Desktop/path/to/changed_jnskfjnes_filename.c".to_string(),
+        let filenames: Vec<PathBuf> = vec![
+            "/Desktop/path/to/changed_A_filename.txt".into(),
+            "/Desktop/path/to/changed_B_filename.jpg".into(),
+            "/Desktop/path/to/changed__filename.gif".into(),
+            "/Desktop/path/to/changed_jnskfjnes_filename.c".into(),
         ];
         assert_eq!(
             MoveBuilder::new(template, &filenames),
             MoveBuilder {
                 asterisk_number: 2,
-                filenames: filenames,
+                filenames,
                 asterisk_sequences: vec![
-                    vec!["A".to_string(), "txt".to_string()],
-                    vec!["B".to_string(), "jpg".to_string()],
-                    vec!["".to_string(), "gif".to_string()],
-                    vec!["jnskfjnes".to_string(), "c".to_string()]
+                    vec!["A".into(), "txt".into()],
+                    vec!["B".into(), "jpg".into()],
+                    vec!["".into(), "gif".into()],
+                    vec!["jnskfjnes".into(), "c".into()]
                 ]
             }
         );
         assert_eq!(
-            MoveBuilder::new(template, &vec![]),
+            MoveBuilder::new::<PathBuf>(template, &[]),
             MoveBuilder {
-                asterisk_number: 0,
+                asterisk_number: 2,
                 filenames: vec![],
                 asterisk_sequences: vec![]
             }
@@ -207,38 +622,26 @@ mod tests {
     #[test]
     fn test_parsing_template2() {
         let template = "/Documents/music/*/* - *.mp3";
-        let filenames = vec![
-            "/Documents/music/pop/ - Maroon5.mp3".to_string(),
-            "/Documents/music/pop/Neizvesten  - Bez nazvania.mp3".to_string(),
-            "/Documents/music/rock/A - B.mp3".to_string(),
-            "/Documents/music/rock/B - D.mp3".to_string(),
-            "/Documents/music/vk/Neizvesten - Bez nazvania.mp3".to_string(),
-            "/Documents/music/vk/Izvesten - S nazvaniem.mp3".to_string(),
+        let filenames: Vec<PathBuf> = vec![
+            "/Documents/music/pop/ - Maroon5.mp3".into(),
+            "/Documents/music/pop/Neizvesten  - Bez nazvania.mp3".into(),
+            "/Documents/music/rock/A - B.mp3".into(),
+            "/Documents/music/rock/B - D.mp3".into(),
+            "/Documents/music/vk/Neizvesten - Bez nazvania.mp3".into(),
+            "/Documents/music/vk/Izvesten - S nazvaniem.mp3".into(),
         ];
         assert_eq!(
             MoveBuilder::new(template, &filenames),
             MoveBuilder {
                 asterisk_number: 3,
-                filenames: filenames,
+                filenames,
                 asterisk_sequences: vec![
-                    vec!["pop".to_string(), "".to_string(), "Maroon5".to_string()],
-                    vec![
-                        "pop".to_string(),
-                        "Neizvesten ".to_string(),
-                        "Bez nazvania".to_string()
-                    ],
-                    vec!["rock".to_string(), "A".to_string(), "B".to_string()],
-                    vec!["rock".to_string(), "B".to_string(), "D".to_string()],
-                    vec![
-                        "vk".to_string(),
-                        "Neizvesten".to_string(),
-                        "Bez nazvania".to_string()
-                    ],
-                    vec![
-                        "vk".to_string(),
-                        "Izvesten".to_string(),
-                        "S nazvaniem".to_string()
-                    ]
+                    vec!["pop".into(), "".into(), "Maroon5".into()],
+                    vec!["pop".into(), "Neizvesten ".into(), "Bez nazvania".into()],
+                    vec!["rock".into(), "A".into(), "B".into()],
+                    vec!["rock".into(), "B".into(), "D".into()],
+                    vec!["vk".into(), "Neizvesten".into(), "Bez nazvania".into()],
+                    vec!["vk".into(), "Izvesten".into(), "S nazvaniem".into()]
                 ]
             }
         );
@@ -247,40 +650,207 @@ mod tests {
     #[test]
     fn test_parsing_template3() {
         let template = "/Documents/music/* - *.mp3";
-        let filenames = vec![
-            "/Documents/music/pop/ - Maroon5.mp3".to_string(),
-            "/Documents/music/pop/Neizvesten  - Bez nazvania.mp3".to_string(),
-            "/Documents/music/rock/A - B.mp3".to_string(),
-            "/Documents/music/rock/B - D.mp3".to_string(),
-            "/Documents/music/vk/Neizvesten - Bez nazvania.mp3".to_string(),
-            "/Documents/music/vk/Izvesten - S nazvaniem.mp3".to_string(),
-            "/Documents/music/vk/to/path/Neizvesten - Bez nazvania.mp3".to_string(),
-            "/Documents/music/Neizvesten - Bez nazvania.mp3".to_string(),
-            "/Documents/music/ - Bez nazvania.mp3".to_string(),
-            "/Documents/music/vk/vk/vk/vk/vk/ -  .mp3".to_string(),
+        let filenames: Vec<PathBuf> = vec![
+            "/Documents/music/pop/ - Maroon5.mp3".into(),
+            "/Documents/music/pop/Neizvesten  - Bez nazvania.mp3".into(),
+            "/Documents/music/rock/A - B.mp3".into(),
+            "/Documents/music/rock/B - D.mp3".into(),
+            "/Documents/music/vk/Neizvesten - Bez nazvania.mp3".into(),
+            "/Documents/music/vk/Izvesten - S nazvaniem.mp3".into(),
+            "/Documents/music/vk/to/path/Neizvesten - Bez nazvania.mp3".into(),
+            "/Documents/music/Neizvesten - Bez nazvania.mp3".into(),
+            "/Documents/music/ - Bez nazvania.mp3".into(),
+            "/Documents/music/vk/vk/vk/vk/vk/ -  .mp3".into(),
         ];
         assert_eq!(
             MoveBuilder::new(template, &filenames),
             MoveBuilder {
                 asterisk_number: 2,
-                filenames: filenames,
+                filenames,
+                asterisk_sequences: vec![
+                    vec!["pop/".into(), "Maroon5".into()],
+                    vec!["pop/Neizvesten ".into(), "Bez nazvania".into()],
+                    vec!["rock/A".into(), "B".into()],
+                    vec!["rock/B".into(), "D".into()],
+                    vec!["vk/Neizvesten".into(), "Bez nazvania".into()],
+                    vec!["vk/Izvesten".into(), "S nazvaniem".into()],
+                    vec!["vk/to/path/Neizvesten".into(), "Bez nazvania".into()],
+                    vec!["Neizvesten".into(), "Bez nazvania".into()],
+                    vec!["".into(), "Bez nazvania".into()],
+                    vec!["vk/vk/vk/vk/vk/".into(), " ".into()]
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn test_parsing_template_ignore_case() {
+        let template = "/Camera/IMG_*.jpg";
+        let filenames: Vec<PathBuf> = vec![
+            "/Camera/IMG_0001.jpg".into(),
+            "/Camera/img_0002.JPG".into(),
+            "/Camera/Img_Holiday.Jpg".into(),
+        ];
+        let ignore_case = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        assert_eq!(
+            MoveBuilder::with_options(template, &filenames, ignore_case),
+            MoveBuilder {
+                asterisk_number: 1,
+                filenames,
+                asterisk_sequences: vec![
+                    vec!["0001".into()],
+                    vec!["0002".into()],
+                    vec!["Holiday".into()]
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn test_parsing_template_hidden() {
+        let template = "/project/*.*";
+        let filenames: Vec<PathBuf> = vec![
+            "/project/.env.local".into(),
+            "/project/main.rs".into(),
+            "/project/.gitkeep".into(),
+        ];
+        let no_hidden = MatchOptions {
+            require_literal_leading_dot: true,
+            ..MatchOptions::new()
+        };
+        assert_eq!(
+            MoveBuilder::with_options(template, &filenames, no_hidden),
+            MoveBuilder {
+                asterisk_number: 2,
+                filenames: vec!["/project/main.rs".into()],
+                asterisk_sequences: vec![vec!["main".into(), "rs".into()]]
+            }
+        );
+        assert_eq!(
+            MoveBuilder::with_options(template, &filenames, MatchOptions::new()),
+            MoveBuilder {
+                asterisk_number: 2,
+                filenames: filenames.clone(),
+                asterisk_sequences: vec![
+                    vec!["".into(), "env.local".into()],
+                    vec!["main".into(), "rs".into()],
+                    vec!["".into(), "gitkeep".into()]
+                ]
+            }
+        );
+        assert!(matches_template(
+            "/project/.*",
+            Path::new("/project/.gitkeep"),
+            no_hidden
+        ));
+        assert_eq!(
+            MoveBuilder::with_options("/project/.*", &filenames, no_hidden).filenames,
+            [
+                PathBuf::from("/project/.env.local"),
+                "/project/.gitkeep".into()
+            ]
+        );
+        assert!(!matches_template(
+            "/*/.*",
+            Path::new("/.git/.gitkeep"),
+            no_hidden
+        ));
+        assert!(matches_template(
+            "/project/.*",
+            Path::new("/project/.gitkeep"),
+            MatchOptions::new()
+        ));
+        assert!(matches_template(
+            "/.config/*.toml",
+            Path::new("/.config/mmv.toml"),
+            no_hidden
+        ));
+        // '*' may take '/' here, but never the '.' that starts a name
+        assert!(!matches_template(
+            "/project/*",
+            Path::new("/project/src/.env"),
+            no_hidden
+        ));
+        assert!(matches_template(
+            "/project/*/.env",
+            Path::new("/project/src/.env"),
+            no_hidden
+        ));
+        assert!(matches_template(
+            "/*",
+            Path::new("/project/./main.rs"),
+            no_hidden
+        ));
+    }
+
+    #[test]
+    fn test_parsing_template_glob_wildcards() {
+        let filenames: Vec<PathBuf> = vec![
+            "/photos/file1.txt".into(),
+            "/photos/file2.txt".into(),
+            "/photos/file3.txt".into(),
+            "/photos/file10.txt".into(),
+        ];
+        assert_eq!(
+            MoveBuilder::new("/photos/file?.txt", &filenames),
+            MoveBuilder {
+                asterisk_number: 1,
+                filenames: filenames[..3].to_vec(),
+                asterisk_sequences: vec![vec!["1".into()], vec!["2".into()], vec!["3".into()]]
+            }
+        );
+        assert_eq!(
+            MoveBuilder::new("/photos/file[12]*.txt", &filenames),
+            MoveBuilder {
+                asterisk_number: 2,
+                filenames: vec![
+                    "/photos/file1.txt".into(),
+                    "/photos/file2.txt".into(),
+                    "/photos/file10.txt".into()
+                ],
                 asterisk_sequences: vec![
-                    vec!["pop/".to_string(), "Maroon5".to_string()],
-                    vec!["pop/Neizvesten ".to_string(), "Bez nazvania".to_string()],
-                    vec!["rock/A".to_string(), "B".to_string()],
-                    vec!["rock/B".to_string(), "D".to_string()],
-                    vec!["vk/Neizvesten".to_string(), "Bez nazvania".to_string()],
-                    vec!["vk/Izvesten".to_string(), "S nazvaniem".to_string()],
-                    vec![
-                        "vk/to/path/Neizvesten".to_string(),
-                        "Bez nazvania".to_string()
-                    ],
-                    vec!["Neizvesten".to_string(), "Bez nazvania".to_string()],
-                    vec!["".to_string(), "Bez nazvania".to_string()],
-                    vec!["vk/vk/vk/vk/vk/".to_string(), " ".to_string()]
+                    vec!["1".into(), "".into()],
+                    vec!["2".into(), "".into()],
+                    vec!["1".into(), "0".into()]
                 ]
             }
         );
+        assert_eq!(
+            MoveBuilder::new("/photos/file[!1-2].txt", &filenames).filenames,
+            [PathBuf::from("/photos/file3.txt")]
+        );
+        assert!(matches_template(
+            "/[draft]/*.txt",
+            Path::new("/d/a.txt"),
+            MatchOptions::new()
+        ));
+        assert!(!matches_template(
+            "/[draft]/*.txt",
+            Path::new("/[draft]/a.txt"),
+            MatchOptions::new()
+        ));
+        assert!(!matches_template(
+            "/photos/file[12.txt",
+            Path::new("/photos/file1.txt"),
+            MatchOptions::new()
+        ));
+        // '**' takes whole directories, the following '*' takes what is left in the name
+        let literal_separator = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let recursive = MoveBuilder::with_options(
+            "/music/**/*.mp3",
+            &["/music/a.mp3", "/music/rock/old/b.mp3"],
+            literal_separator,
+        );
+        assert_eq!(
+            recursive.asterisk_sequences,
+            [["", "a"], ["rock/old/", "b"]].map(|sequence| sequence.map(OsString::from))
+        );
     }
 
     #[test]
@@ -290,11 +860,12 @@ mod tests {
             ParsedTarget::new(template1),
             ParsedTarget {
                 stable_filename_parts: vec![
-                    "/home/Desktop/path/to/changed_".to_string(),
-                    "_filename.".to_string(),
-                    "".to_string()
+                    "/home/Desktop/path/to/changed_".into(),
+                    "_filename.".into(),
+                    "".into()
                 ],
-                template_index_sequence: vec![1, 2]
+                template_index_sequence: vec![1, 2],
+                template_filters: vec![vec![]; 2]
             }
         );
         let template2 = "/home/Desktop/path/to/changed_#1_fil_#2_e_#1_#2_nam_#1_e.#2";
@@ -302,25 +873,25 @@ mod tests {
             ParsedTarget::new(template2),
             ParsedTarget {
                 stable_filename_parts: vec![
-                    "/home/Desktop/path/to/changed_".to_string(),
-                    "_fil_".to_string(),
-                    "_e_".to_string(),
-                    "_".to_string(),
-                    "_nam_".to_string(),
-                    "_e.".to_string(),
-                    "".to_string()
+                    "/home/Desktop/path/to/changed_".into(),
+                    "_fil_".into(),
+                    "_e_".into(),
+                    "_".into(),
+                    "_nam_".into(),
+                    "_e.".into(),
+                    "".into()
                 ],
-                template_index_sequence: vec![1, 2, 1, 2, 1, 2]
+                template_index_sequence: vec![1, 2, 1, 2, 1, 2],
+                template_filters: vec![vec![]; 6]
             }
         );
         let template3 = "/home/Desktop/path/to/changed_filename.txt";
         assert_eq!(
             ParsedTarget::new(template3),
             ParsedTarget {
-                stable_filename_parts: vec![
-                    "/home/Desktop/path/to/changed_filename.txt".to_string()
-                ],
-                template_index_sequence: vec![]
+                stable_filename_parts: vec!["/home/Desktop/path/to/changed_filename.txt".into()],
+                template_index_sequence: vec![],
+                template_filters: vec![]
             }
         );
     }
@@ -329,54 +900,201 @@ mod tests {
     fn test_building_target_files1() {
         let template_from = "/Desktop/path/to/some_*_filename.*";
         let template_to1 = "/home/Desktop/path/to/changed_#1_filename.#2";
-        let filenames = vec![
-            "/Desktop/path/to/some_A_filename.txt".to_string(),
-            "/Desktop/path/to/some_B_filename.jpg".to_string(),
-            "/Desktop/path/to/some__filename.gif".to_string(),
-            "/Desktop/path/to/some_jnskfjnes_filename.c".to_string(),
+        let filenames: Vec<PathBuf> = vec![
+            "/Desktop/path/to/some_A_filename.txt".into(),
+            "/Desktop/path/to/some_B_filename.jpg".into(),
+            "/Desktop/path/to/some__filename.gif".into(),
+            "/Desktop/path/to/some_jnskfjnes_filename.c".into(),
         ];
-        let move_builder = MoveBuilder::new(&template_from, &filenames);
-        let parsed_target1 = ParsedTarget::new(&template_to1);
-        // let result = Result<(Vec<String>, Vec<String>), (usize, usize)>
-        let result1: Result<(Vec<String>, Vec<String>), TemplateError> = Ok((
+        let move_builder = MoveBuilder::new(template_from, &filenames);
+        let parsed_target1 = ParsedTarget::new(template_to1);
+        // let result = Result<(Vec<PathBuf>, Vec<PathBuf>), (usize, usize)>
+        let result1: Result<(Vec<PathBuf>, Vec<PathBuf>), TemplateError> = Ok((
             filenames.clone(),
             vec![
-                "/home/Desktop/path/to/changed_A_filename.txt".to_string(),
-                "/home/Desktop/path/to/changed_B_filename.jpg".to_string(),
-                "/home/Desktop/path/to/changed__filename.gif".to_string(),
-                "/home/Desktop/path/to/changed_jnskfjnes_filename.c".to_string(),
+                "/home/Desktop/path/to/changed_A_filename.txt".into(),
+                "/home/Desktop/path/to/changed_B_filename.jpg".into(),
+                "/home/Desktop/path/to/changed__filename.gif".into(),
+                "/home/Desktop/path/to/changed_jnskfjnes_filename.c".into(),
             ],
         ));
         assert_eq!(move_builder.build_target_names(&parsed_target1), result1);
 
-        let result2: Result<(Vec<String>, Vec<String>), TemplateError> = Err(TemplateError {
-            asterisks: 2,
-            hashes: 3,
-        });
+        let result2: Result<(Vec<PathBuf>, Vec<PathBuf>), TemplateError> =
+            Err(TemplateError::NotEnoughAsterisks {
+                asterisks: 2,
+                hashes: 3,
+            });
         let template_to2 = "/home/Desktop/path/to/changed_#1_fil#3ename.#2";
-        let parsed_target2 = ParsedTarget::new(&template_to2);
+        let parsed_target2 = ParsedTarget::new(template_to2);
         assert_eq!(move_builder.build_target_names(&parsed_target2), result2)
     }
     #[test]
     fn test_building_target_files2() {
         let template_from = "/Desktop/path/to/some_*_filename.*";
         let template_to = "/home/Desktop/path#1/to/changed_#1_#2_#1_#2_#1_filename.#2";
-        let filenames = vec![
-            "/Desktop/path/to/some_A_filename.txt".to_string(),
-            "/Desktop/path/to/some_B_filename.jpg".to_string(),
-            "/Desktop/path/to/some__filename.gif".to_string(),
-            "/Desktop/path/to/some_jnskfjnes_filename.c".to_string(),
+        let filenames: Vec<PathBuf> = vec![
+            "/Desktop/path/to/some_A_filename.txt".into(),
+            "/Desktop/path/to/some_B_filename.jpg".into(),
+            "/Desktop/path/to/some__filename.gif".into(),
+            "/Desktop/path/to/some_jnskfjnes_filename.c".into(),
         ];
-        let move_builder = MoveBuilder::new(&template_from, &filenames);
-        let parsed_target = ParsedTarget::new(&template_to);
-        let result: Result<(Vec<String>, Vec<String>), TemplateError> = Ok((
+        let move_builder = MoveBuilder::new(template_from, &filenames);
+        let parsed_target = ParsedTarget::new(template_to);
+        let result: Result<(Vec<PathBuf>, Vec<PathBuf>), TemplateError> = Ok((
             filenames.clone(),
             vec![
-            "/home/Desktop/pathA/to/changed_A_txt_A_txt_A_filename.txt".to_string(),
-            "/home/Desktop/pathB/to/changed_B_jpg_B_jpg_B_filename.jpg".to_string(),
-            "/home/Desktop/path/to/changed__gif__gif__filename.gif".to_string(),
-            "/home/Desktop/pathjnskfjnes/to/changed_jnskfjnes_c_jnskfjnes_c_jnskfjnes_filename.c".to_string(),
+            "/home/Desktop/pathA/to/changed_A_txt_A_txt_A_filename.txt".into(),
+            "/home/Desktop/pathB/to/changed_B_jpg_B_jpg_B_filename.jpg".into(),
+            "/home/Desktop/path/to/changed__gif__gif__filename.gif".into(),
+            "/home/Desktop/pathjnskfjnes/to/changed_jnskfjnes_c_jnskfjnes_c_jnskfjnes_filename.c".into(),
         ]));
         assert_eq!(move_builder.build_target_names(&parsed_target), result)
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_building_target_files_non_utf8() {
+        use crate::os_strings::name_from_bytes;
+        // CP1251 'Фото' and Latin-1 'é' are kept byte for byte
+        let filenames = vec![PathBuf::from(name_from_bytes(
+            b"/archive/\xd4\xee\xf2\xee_caf\xe9.txt".to_vec(),
+        ))];
+        let move_builder = MoveBuilder::new("/archive/*_*.txt", &filenames);
+        let (initial_filenames, target_filenames) = move_builder
+            .build_target_names(&ParsedTarget::new("/archive/#2 #1.txt"))
+            .unwrap();
+        assert_eq!(initial_filenames, filenames);
+        assert_eq!(
+            target_filenames,
+            [PathBuf::from(name_from_bytes(
+                b"/archive/caf\xe9 \xd4\xee\xf2\xee.txt".to_vec()
+            ))]
+        );
+        // Invalid bytes are not taken for U+FFFD they are shown with
+        assert!(!matches_template(
+            "/archive/\u{fffd}*",
+            &filenames[0],
+            MatchOptions::new()
+        ));
+        assert!(matches_template(
+            "/archive/*_CAF*.txt",
+            &filenames[0],
+            MatchOptions {
+                case_sensitive: false,
+                ..MatchOptions::new()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_building_target_files_translit() {
+        let filenames: Vec<PathBuf> = vec![
+            "/music/\u{41d}\u{435}\u{438}\u{437}\u{432}\u{435}\u{441}\u{442}\u{435}\u{43d}.mp3"
+                .into(),
+            "/music/\u{401}\u{43b}\u{43a}\u{430}.mp3".into(),
+        ];
+        let move_builder = MoveBuilder::new("/music/*.mp3", &filenames);
+        let parsed_target = ParsedTarget::new("/latin/#{1|translit} (#1).mp3");
+        assert_eq!(parsed_target.template_index_sequence, [1, 1]);
+        assert_eq!(
+            parsed_target.template_filters,
+            [vec!["translit".to_string()], vec![]]
+        );
+        let (_, target_filenames) = move_builder.build_target_names(&parsed_target).unwrap();
+        assert_eq!(
+            target_filenames,
+            [
+                PathBuf::from("/latin/Neizvesten (\u{41d}\u{435}\u{438}\u{437}\u{432}\u{435}\u{441}\u{442}\u{435}\u{43d}).mp3"),
+                PathBuf::from("/latin/Iolka (\u{401}\u{43b}\u{43a}\u{430}).mp3"),
+            ]
+        );
+        // Decomposed (NFD) 'ё' is transliterated as the composed one
+        let decomposed = vec![PathBuf::from(
+            "/music/\u{435}\u{308}\u{43b}\u{43a}\u{430}.mp3",
+        )];
+        assert_eq!(
+            MoveBuilder::new("/music/*.mp3", &decomposed)
+                .build_target_names(&ParsedTarget::new("/latin/#{1|translit}.mp3"))
+                .unwrap()
+                .1,
+            [PathBuf::from("/latin/iolka.mp3")]
+        );
+        assert_eq!(
+            move_builder.build_target_names(&ParsedTarget::new("/latin/#{1|upper}.mp3")),
+            Err(TemplateError::UnknownFilter("upper".into()))
+        );
+    }
+
+    #[test]
+    fn test_template_normalization() {
+        let file_system = MemoryFileSystem::new();
+        // Decomposed 'ё' (NFD), as files copied from macOS have it
+        let decomposed = "music/\u{435}\u{308}lka.txt";
+        file_system.add_file(decomposed, "");
+        file_system.add_file("music/plain.txt", "");
+        let template = Template::new("music/\u{451}*.txt", "music/#1.txt");
+        assert_eq!(
+            template.find_files_in(&file_system),
+            Err(MmvError::NoFiles("music/\u{451}*.txt".into()))
+        );
+        let template = template.with_normalization(NormalForm::Nfc, false);
+        let filenames = template.find_files_in(&file_system).unwrap();
+        assert_eq!(filenames, [PathBuf::from(decomposed)]);
+        assert!(template.matches(Path::new(decomposed)));
+        let plan = template.plan(&filenames).unwrap();
+        assert_eq!(plan.initial_filenames, [PathBuf::from(decomposed)]);
+        assert_eq!(plan.target_filenames, [PathBuf::from("music/lka.txt")]);
+
+        // Captured sequences are already normalized, the target template is taken as it is
+        let template = Template::new("music/*.txt", "music/\u{435}\u{308}_#1.txt");
+        let filenames = [decomposed, "music/plain.txt"];
+        assert_eq!(
+            template
+                .clone()
+                .with_normalization(NormalForm::Nfc, false)
+                .plan(&filenames)
+                .unwrap()
+                .target_filenames[0],
+            PathBuf::from("music/\u{435}\u{308}_\u{451}lka.txt")
+        );
+        assert_eq!(
+            template
+                .with_normalization(NormalForm::Nfc, true)
+                .plan(&filenames)
+                .unwrap()
+                .target_filenames,
+            [
+                PathBuf::from("music/\u{451}_\u{451}lka.txt"),
+                PathBuf::from("music/\u{451}_plain.txt")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_plan() {
+        let filenames: Vec<PathBuf> =
+            vec!["/Desktop/IMG_0001.JPEG".into(), "/Desktop/notes.txt".into()];
+        let template = Template::new("/Desktop/IMG_*.jpeg", "/Desktop/photo_#1.jpg")
+            .with_match_options(MatchOptions {
+                case_sensitive: false,
+                ..MatchOptions::new()
+            });
+        let plan = template.plan(&filenames).unwrap();
+        assert_eq!(
+            plan.initial_filenames,
+            [PathBuf::from("/Desktop/IMG_0001.JPEG")]
+        );
+        assert_eq!(
+            plan.target_filenames,
+            [PathBuf::from("/Desktop/photo_0001.jpg")]
+        );
+        assert_eq!(
+            Template::new("/Desktop/*.txt", "/Desktop/#2.txt").plan(&filenames),
+            Err(MmvError::Template(TemplateError::NotEnoughAsterisks {
+                asterisks: 1,
+                hashes: 2,
+            }))
+        );
+    }
 }
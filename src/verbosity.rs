@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    /// How much mmv tells about its work. Errors are printed at every level
+    /// Nothing but errors ('-q')
+    Quiet,
+    /// Moved files and the result
+    Normal,
+    /// Matched files and the sequences captured from every file ('-v')
+    Verbose,
+    /// Every rename call on the filesystem too ('-vv')
+    Debug,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+impl Verbosity {
+    /// Level by the command line flags: quiet wins, every '-v' adds a level
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Debug,
+        }
+    }
+}
+
+/// Sets the level for the whole process, it is Normal until set
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::Debug,
+    }
+}
+
+/// Prints the message to the standard error if the level is at least the given one.
+/// The message is built only when it is printed
+pub fn log(level: Verbosity, message: impl FnOnce() -> String) {
+    if verbosity() >= level {
+        eprintln!("mmv: {}", message());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::verbosity::Verbosity;
+
+    #[test]
+    fn test_verbosity_from_flags() {
+        assert_eq!(Verbosity::from_flags(true, 2), Verbosity::Quiet);
+        assert_eq!(Verbosity::from_flags(false, 0), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(false, 1), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_flags(false, 3), Verbosity::Debug);
+        assert!(Verbosity::Debug > Verbosity::Verbose);
+    }
+}
@@ -1,14 +1,14 @@
-use std::fs::{create_dir, create_dir_all, remove_dir_all, File};
-pub static ROOT_DIRECTORY_NAME: &str = "dehftcbt4yu3h53r5435ergieruh";
-use std::env::current_dir;
+use std::fs::{create_dir_all, File};
 use std::path::Path;
+use tempfile::TempDir;
+
 /// Making up catalogue with some files we will test mmv on
 ///
-/// Creating folder with the name that very unlikely to be present in the current directory
+/// Every test gets its own temporary directory, which is removed when the returned value is dropped
 /// All the tests are supposed to move files inside it not to ruin anything on pc
-pub fn setup_env() {
-    let path = current_dir().unwrap().to_string_lossy().to_string() + "/" + ROOT_DIRECTORY_NAME;
-    let _ = create_dir(&path);
+pub fn setup_env() -> TempDir {
+    let directory = tempfile::tempdir().unwrap();
+    let path = root_of(&directory);
     let files_directory1: Vec<String> = vec![
         "/path/to/some_A_filename.txt".to_string(),
         "/path/to/some_B_filename.jpg".to_string(),
@@ -28,18 +28,17 @@ pub fn setup_env() {
         "/Documents/music/vk/vk/vk/vk/vk/ -  .mp3".to_string(),
     ];
     for filename in &files_directory1 {
-        let mut full_path = path.clone(); //ROOT_DIRECTORY_NAME.to_string().clone();
-                                          // full_path.push_str(&directory1);
-        full_path.push_str(&filename);
+        let mut full_path = path.clone();
+        full_path.push_str(filename);
         let pathbuf_path = Path::new(&full_path);
         let path_prefix = pathbuf_path.parent().unwrap();
         let _ = create_dir_all(path_prefix);
         let _ = File::create(&full_path);
     }
+    directory
 }
 
-pub fn destroy_env() {
-    let _ = remove_dir_all(
-        current_dir().unwrap().to_string_lossy().to_string() + "/" + ROOT_DIRECTORY_NAME,
-    );
+/// Path of the test catalogue, the way it is passed to mmv
+pub fn root_of(directory: &TempDir) -> String {
+    directory.path().to_string_lossy().to_string()
 }
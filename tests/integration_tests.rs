@@ -1,11 +1,10 @@
 mod common;
 use assert_cmd::Command;
-use common::{destroy_env, setup_env, ROOT_DIRECTORY_NAME};
+use common::{root_of, setup_env};
 #[test]
 fn integration_test1() {
-    destroy_env();
-    setup_env();
-    let root = ROOT_DIRECTORY_NAME.to_string();
+    let directory = setup_env();
+    let root = root_of(&directory);
     let mut command = Command::cargo_bin("mmv").unwrap();
     let arguments = vec![
         root.clone() + "/path/to/some_*_filename.*",
@@ -31,40 +30,38 @@ fn integration_test1() {
             + "/path/to/changed_jnskfjnes_filename.c\n"
             + "mmv: Succeded!\n",
     );
-    destroy_env();
 }
 
 #[test]
 fn integration_test2() {
-    destroy_env();
-    setup_env();
-    let root = ROOT_DIRECTORY_NAME.to_string();
+    let directory = setup_env();
+    let root = root_of(&directory);
     let mut command = Command::cargo_bin("mmv").unwrap();
     let arguments = vec![
         root.clone() + "/path/to/some__*_filename.*",
         root.clone() + "/path/to/changed_#1_filename.#2",
     ];
     let assert1 = command.args(&arguments).assert();
-    assert1.failure().code(1)
-    .stdout("mmv: Files for pattern 'dehftcbt4yu3h53r5435ergieruh/path/to/some__*_filename.*' not found\n");
-    destroy_env();
+    assert1.failure().code(1).stderr(format!(
+        "mmv: Files for pattern '{}/path/to/some__*_filename.*' not found\n",
+        root
+    ));
 }
 
 #[test]
 fn integration_test3() {
-    destroy_env();
-    setup_env();
-    let root = ROOT_DIRECTORY_NAME.to_string();
+    let directory = setup_env();
+    let root = root_of(&directory);
     let mut command = Command::cargo_bin("mmv").unwrap();
     let arguments = vec![
         root.clone() + "/path/to/some_*_filename.*",
         root.clone() + "/path/to/some_#1_filename.#2",
     ];
     let assert1 = command.args(&arguments).assert();
-    assert1.failure().code(1).stdout(
-        "mmv: Not able to replace existing file: ".to_string()
+    assert1.failure().code(4).stderr(
+        "mmv: Not able to replace existing file: '".to_string()
             + &root
-            + "/path/to/some_A_filename.txt\n",
+            + "/path/to/some_A_filename.txt'\n",
     );
 
     let mut command2 = Command::cargo_bin("mmv").unwrap();
@@ -75,6 +72,815 @@ fn integration_test3() {
     ];
     let assert2 = command2.args(&arguments2).assert();
     assert2.success();
+}
+
+#[test]
+fn integration_test_ignore_case() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_*_FILENAME.TXT",
+        root.clone() + "/path/to/changed_#1_filename.txt",
+    ];
+    command.args(&arguments).assert().failure().code(1);
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/path/to/some_*_FILENAME.TXT",
+        root.clone() + "/path/to/changed_#1_filename.txt",
+        "--ignore-case".to_string(),
+    ];
+    command2.args(&arguments2).assert().success().stdout(
+        root.clone()
+            + "/path/to/some_A_filename.txt -> "
+            + &root
+            + "/path/to/changed_A_filename.txt\n"
+            + "mmv: Succeded!\n",
+    );
+}
+
+#[test]
+fn integration_test_no_hidden() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let _ = std::fs::File::create(root.clone() + "/path/to/.gitkeep");
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/*",
+        root.clone() + "/path/to/old_#1",
+        "--no-hidden".to_string(),
+    ];
+    command.args(&arguments).assert().success();
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/.gitkeep")).exists());
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/old_some__filename.gif")).exists());
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/path/to/.*",
+        root.clone() + "/path/to/#1",
+        "--no-hidden".to_string(),
+        "--hidden".to_string(),
+    ];
+    command2.args(&arguments2).assert().success().stdout(
+        root.clone() + "/path/to/.gitkeep -> " + &root + "/path/to/gitkeep\n" + "mmv: Succeded!\n",
+    );
+}
+
+#[test]
+fn integration_test_exclude() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let exclude_file = root.clone() + "/exclude.txt";
+    let _ = std::fs::write(&exclude_file, "# keep pictures\n*.gif\n");
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_*_filename.*",
+        root.clone() + "/path/to/changed_#1_filename.#2",
+        "--exclude".to_string(),
+        "*.txt".to_string(),
+        "--exclude".to_string(),
+        "*_jnskfjnes_*".to_string(),
+        "--exclude-from".to_string(),
+        exclude_file,
+    ];
+    command.args(&arguments).assert().success().stdout(
+        root.clone()
+            + "/path/to/some_B_filename.jpg -> "
+            + &root
+            + "/path/to/changed_B_filename.jpg\n"
+            + "mmv: Succeded!\n",
+    );
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/path/to/some_*_filename.*",
+        root.clone() + "/path/to/changed_#1_filename.#2",
+        "--exclude".to_string(),
+        "some_*".to_string(),
+    ];
+    command2
+        .args(&arguments2)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(
+            "mmv: Files for pattern '".to_string()
+                + &root
+                + "/path/to/some_*_filename.*' not found\n",
+        );
+}
+
+#[test]
+fn integration_test_file_filters() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let _ = std::fs::write(
+        root.clone() + "/Documents/music/rock/A - B.mp3",
+        [0u8; 2048],
+    );
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/Documents/music/*",
+        root.clone() + "/Documents/music/old_#1",
+        "--type".to_string(),
+        "d".to_string(),
+    ];
+    command.args(&arguments).assert().success();
+    assert!(std::path::Path::new(&(root.clone() + "/Documents/music/old_rock")).is_dir());
+    assert!(std::path::Path::new(
+        &(root.clone() + "/Documents/music/Neizvesten - Bez nazvania.mp3")
+    )
+    .exists());
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/Documents/music/old_rock/*.mp3",
+        root.clone() + "/Documents/music/old_rock/big_#1.mp3",
+        "--type=f".to_string(),
+        "--min-size=1K".to_string(),
+        "--newer-than=1h".to_string(),
+    ];
+    command2.args(&arguments2).assert().success().stdout(
+        root.clone()
+            + "/Documents/music/old_rock/A - B.mp3 -> "
+            + &root
+            + "/Documents/music/old_rock/big_A - B.mp3\n"
+            + "mmv: Succeded!\n",
+    );
 
-    destroy_env();
+    let mut command3 = Command::cargo_bin("mmv").unwrap();
+    let arguments3 = vec![
+        root.clone() + "/Documents/music/old_rock/*.mp3",
+        root.clone() + "/Documents/music/old_rock/#1.mp3",
+        "--max-size=lots".to_string(),
+    ];
+    command3.args(&arguments3).assert().failure().code(2);
+}
+
+#[test]
+fn integration_test_from_stdin() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/Documents/music/*/* - *.mp3",
+        root.clone() + "/Documents/music/#1/#3 by #2.mp3",
+        "--from-stdin".to_string(),
+    ];
+    let files_list = root.clone()
+        + "/Documents/music/rock/A - B.mp3\n"
+        + &root
+        + "/Documents/music/rock/A-B.mp3\n";
+    command
+        .args(&arguments)
+        .write_stdin(files_list)
+        .assert()
+        .success()
+        .stdout(
+            root.clone()
+                + "/Documents/music/rock/A - B.mp3 -> "
+                + &root
+                + "/Documents/music/rock/B by A.mp3\n"
+                + "mmv: Succeded!\n",
+        )
+        .stderr(
+            "mmv: File '".to_string()
+                + &root
+                + "/Documents/music/rock/A-B.mp3' does not suit pattern '"
+                + &root
+                + "/Documents/music/*/* - *.mp3', skipped\n",
+        );
+    assert!(std::path::Path::new(&(root.clone() + "/Documents/music/rock/B - D.mp3")).exists());
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/path/to/some_*",
+        root.clone() + "/path/to/#1",
+        "--from-stdin".to_string(),
+        "-0".to_string(),
+    ];
+    let files_list2 =
+        root.clone() + "/path/to/some_A_filename.txt\0" + &root + "/path/to/some_B_filename.jpg\0";
+    command2
+        .args(&arguments2)
+        .write_stdin(files_list2)
+        .assert()
+        .success();
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/A_filename.txt")).exists());
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/B_filename.jpg")).exists());
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/some__filename.gif")).exists());
+}
+
+#[test]
+fn integration_test_map() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let map_file = root.clone() + "/renames.csv";
+    let first = root.clone() + "/path/to/some_A_filename.txt";
+    let second = root.clone() + "/path/to/some_B_filename.jpg";
+    let _ = std::fs::write(&first, "first");
+    let _ = std::fs::write(
+        &map_file,
+        format!("old,new\n{first},{second}\n{second},{first}\n"),
+    );
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    command
+        .args(["--map", &map_file])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{first} -> {second}\n{second} -> {first}\nmmv: Succeded!\n"
+        ));
+    assert_eq!(std::fs::read_to_string(&second).unwrap(), "first");
+    assert_eq!(std::fs::read_to_string(&first).unwrap(), "");
+
+    let _ = std::fs::write(&map_file, format!("{first},{second}\n"));
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    command2
+        .args(["--map", &map_file])
+        .assert()
+        .failure()
+        .code(4)
+        .stderr(format!(
+            "mmv: Not able to replace existing file: '{second}'\n"
+        ));
+}
+
+#[test]
+fn integration_test_edit() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_*_filename.*",
+        root.clone() + "/path/to/changed_#1_filename.#2",
+        "--edit".to_string(),
+    ];
+    command
+        .env_remove("VISUAL")
+        .env(
+            "EDITOR",
+            "sed -i -e s/changed_B/edited_B/ -e /some__filename/,+1d",
+        )
+        .args(&arguments)
+        .assert()
+        .success();
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/edited_B_filename.jpg")).exists());
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/changed_A_filename.txt")).exists());
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/some__filename.gif")).exists());
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![root.clone() + "/path/to/changed_*", "--edit".to_string()];
+    command2
+        .env_remove("VISUAL")
+        .env("EDITOR", "false")
+        .args(&arguments2)
+        .assert()
+        .failure()
+        .code(6)
+        .stderr("mmv: Editor 'false' failed, nothing is moved\n");
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/changed_A_filename.txt")).exists());
+}
+
+#[test]
+fn integration_test_edit_from_stdin() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    command
+        .env_remove("VISUAL")
+        .env("EDITOR", "sed -i -e s/changed_A/edited_A/")
+        .args([
+            root.clone() + "/path/to/some_*_filename.*",
+            root.clone() + "/path/to/changed_#1_filename.#2",
+            "--from-stdin".to_string(),
+            "--edit".to_string(),
+        ])
+        .write_stdin(root.clone() + "/path/to/some_A_filename.txt\n")
+        .assert()
+        .success();
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/edited_A_filename.txt")).exists());
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/some_B_filename.jpg")).exists());
+}
+
+#[test]
+fn integration_test_conflict_policy() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let _ = std::fs::File::create(root.clone() + "/path/to/changed_B_filename.jpg");
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_*_filename.*",
+        root.clone() + "/path/to/changed_#1_filename.#2",
+        "--on-conflict".to_string(),
+        "rename".to_string(),
+    ];
+    command.args(&arguments).assert().success().stdout(
+        root.clone()
+            + "/path/to/some_A_filename.txt -> "
+            + &root
+            + "/path/to/changed_A_filename.txt\n"
+            + &root
+            + "/path/to/some_B_filename.jpg -> "
+            + &root
+            + "/path/to/changed_B_filename (1).jpg (target exists, renamed)\n"
+            + &root
+            + "/path/to/some__filename.gif -> "
+            + &root
+            + "/path/to/changed__filename.gif\n"
+            + &root
+            + "/path/to/some_jnskfjnes_filename.c -> "
+            + &root
+            + "/path/to/changed_jnskfjnes_filename.c\n"
+            + "mmv: Succeded!\n",
+    );
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/path/to/changed_B_filename (1).*",
+        root.clone() + "/path/to/changed_B_filename.#1",
+        "--on-conflict=skip".to_string(),
+    ];
+    command2.args(&arguments2).assert().success().stdout(
+        root.clone()
+            + "/path/to/changed_B_filename (1).jpg -> "
+            + &root
+            + "/path/to/changed_B_filename.jpg (skipped, target exists)\n"
+            + "mmv: Succeded!\n",
+    );
+
+    let mut command3 = Command::cargo_bin("mmv").unwrap();
+    let arguments3 = vec![
+        root.clone() + "/path/to/changed_B_filename (1).*",
+        root.clone() + "/path/to/changed_B_filename.#1",
+        "--on-conflict=skip".to_string(),
+        "--force".to_string(),
+    ];
+    command3.args(&arguments3).assert().failure().code(2);
+}
+
+#[test]
+fn integration_test_backup() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let target = root.clone() + "/path/to/changed_A_filename.txt";
+    let _ = std::fs::write(&target, "old");
+    let _ = std::fs::write(root.clone() + "/path/to/some_A_filename.txt", "new");
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_A_*",
+        root.clone() + "/path/to/changed_A_#1",
+        "--force".to_string(),
+        "--backup=simple".to_string(),
+        "--suffix=.bak".to_string(),
+    ];
+    command.args(&arguments).assert().success().stdout(format!(
+        "{root}/path/to/some_A_filename.txt -> {target} (overwritten, backup '{target}.bak')\n\
+         mmv: Succeded!\n"
+    ));
+    assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+    assert_eq!(
+        std::fs::read_to_string(target.clone() + ".bak").unwrap(),
+        "old"
+    );
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/path/to/some_B_*",
+        root.clone() + "/path/to/changed_A_filename.txt",
+        "--on-conflict=backup".to_string(),
+    ];
+    command2.args(&arguments2).assert().success();
+    assert!(std::path::Path::new(&(target.clone() + "~")).exists());
+}
+
+#[test]
+fn integration_test_swap() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let live = root.clone() + "/path/to/changed_A_filename.txt";
+    let staging = root.clone() + "/path/to/some_A_filename.txt";
+    let _ = std::fs::write(&live, "live");
+    let _ = std::fs::write(&staging, "staging");
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_A_*",
+        root.clone() + "/path/to/changed_A_#1",
+        "--swap".to_string(),
+    ];
+    command.args(&arguments).assert().success().stdout(format!(
+        "{staging} <-> {live} (swapped)\n\
+         mmv: Succeded!\n"
+    ));
+    assert_eq!(std::fs::read_to_string(&live).unwrap(), "staging");
+    assert_eq!(std::fs::read_to_string(&staging).unwrap(), "live");
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/path/to/some_A_*",
+        root.clone() + "/path/to/changed_A_#1",
+        "--swap".to_string(),
+        "--force".to_string(),
+    ];
+    command2.args(&arguments2).assert().failure().code(2);
+}
+
+#[test]
+fn integration_test_keep_going() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let _ = std::fs::write(root.clone() + "/path/to/changed_B_filename.jpg", "old");
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_*_filename.*",
+        root.clone() + "/path/to/changed_#1_filename.#2",
+        "--keep-going".to_string(),
+    ];
+    command
+        .args(&arguments)
+        .assert()
+        .failure()
+        .code(5)
+        .stderr(format!(
+            "mmv: Not able to replace existing file: '{root}/path/to/changed_B_filename.jpg'\n"
+        ))
+        .stdout(format!(
+            "{root}/path/to/some_A_filename.txt -> {root}/path/to/changed_A_filename.txt\n\
+             {root}/path/to/some__filename.gif -> {root}/path/to/changed__filename.gif\n\
+             {root}/path/to/some_jnskfjnes_filename.c -> {root}/path/to/changed_jnskfjnes_filename.c\n\
+             mmv: Summary\n  \
+             succeeded  3\n  \
+             skipped    0\n  \
+             failed     1\n             \
+             {root}/path/to/some_B_filename.jpg -> {root}/path/to/changed_B_filename.jpg\n"
+        ));
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/some_B_filename.jpg")).exists());
+    assert!(!std::path::Path::new(&(root.clone() + "/path/to/some_A_filename.txt")).exists());
+}
+
+#[test]
+fn integration_test_exit_codes() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_*_filename.*",
+        root.clone() + "/path/to/changed_#3_filename.#2",
+    ];
+    command.args(&arguments).assert().failure().code(3);
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/path/to/some_[*_filename.*",
+        root.clone() + "/path/to/changed_#1_filename.#2",
+    ];
+    let assert2 = command2
+        .args(&arguments2)
+        .assert()
+        .failure()
+        .code(3)
+        .stdout("");
+    assert!(
+        String::from_utf8_lossy(&assert2.get_output().stderr).starts_with(&format!(
+            "mmv: Invalid pattern '{root}/path/to/some_[*_filename.*'"
+        ))
+    );
+
+    let mut command3 = Command::cargo_bin("mmv").unwrap();
+    let arguments3 = vec![
+        root.clone() + "/path/to/some_*_filename.*",
+        root.clone() + "/path/to/same.txt",
+    ];
+    command3
+        .args(&arguments3)
+        .assert()
+        .failure()
+        .code(4)
+        .stderr(format!(
+        "mmv: Several files are going to be moved to the same name: '{root}/path/to/same.txt'\n"
+    ));
+}
+
+#[test]
+fn integration_test_json_output() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_A_*",
+        root.clone() + "/path/to/changed_A_#1",
+        "--output=jsonl".to_string(),
+    ];
+    command.args(&arguments).assert().success().stdout(format!(
+        "{{\"type\":\"plan\",\"source\":\"{root}/path/to/some_A_filename.txt\",\"target\":\"{root}/path/to/changed_A_filename.txt\"}}\n\
+         {{\"type\":\"result\",\"source\":\"{root}/path/to/some_A_filename.txt\",\"target\":\"{root}/path/to/changed_A_filename.txt\",\"status\":\"moved\"}}\n\
+         {{\"type\":\"summary\",\"succeeded\":1,\"skipped\":0,\"failed\":0,\"exit_code\":0}}\n"
+    ));
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    let arguments2 = vec![
+        root.clone() + "/path/to/missing_*",
+        root.clone() + "/path/to/changed_#1",
+        "--output=json".to_string(),
+    ];
+    command2
+        .args(&arguments2)
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(format!(
+            "[\n  {{\n    \"type\": \"error\",\n    \"message\": \"mmv: Files for pattern '{root}/path/to/missing_*' not found\",\n    \"exit_code\": 1\n  }}\n]\n"
+        ));
+}
+
+#[test]
+fn integration_test_emit_script() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let _ = std::fs::write(root.clone() + "/path/to/changed_B_filename.jpg", "old");
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_*_filename.*",
+        root.clone() + "/path/to/changed_#1_filename.#2",
+        "--on-conflict=skip".to_string(),
+        "--emit-script=sh".to_string(),
+    ];
+    let output = command.args(&arguments).assert().success();
+    let script = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    assert!(script.starts_with(&format!(
+        "#!/bin/sh\n# Generated by mmv, review before running\nset -e\n\
+         # {root}/path/to/some_B_filename.jpg -> {root}/path/to/changed_B_filename.jpg: skipped, target exists\n\
+         if [ -e '{root}/path/to/changed_A_filename.txt' ]"
+    )));
+    assert!(script.ends_with(&format!(
+        "mv -n -- '{root}/path/to/some_jnskfjnes_filename.c' '{root}/path/to/changed_jnskfjnes_filename.c'\n"
+    )));
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/some_A_filename.txt")).exists());
+
+    let _ = std::fs::write(root.clone() + "/moves.sh", &script);
+    let status = std::process::Command::new("sh")
+        .arg(root.clone() + "/moves.sh")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/changed_A_filename.txt")).exists());
+    assert_eq!(
+        std::fs::read_to_string(root.clone() + "/path/to/changed_B_filename.jpg").unwrap(),
+        "old"
+    );
+}
+
+#[test]
+fn integration_test_plan_and_apply() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let plan_file = root.clone() + "/plan.json";
+    let source = root.clone() + "/path/to/some_A_filename.txt";
+    let target = root.clone() + "/path/to/changed_A_filename.txt";
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        "plan".to_string(),
+        root.clone() + "/path/to/some_A_*",
+        root.clone() + "/path/to/changed_A_#1",
+        "--save".to_string(),
+        plan_file.clone(),
+    ];
+    command.args(&arguments).assert().success().stdout(format!(
+        "{source} -> {target}\nmmv: Plan is saved to '{plan_file}'\n"
+    ));
+    assert!(std::path::Path::new(&source).exists());
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    command2
+        .args(["apply", &plan_file])
+        .assert()
+        .success()
+        .stdout(format!("{source} -> {target}\nmmv: Succeded!\n"));
+    assert!(std::path::Path::new(&target).exists());
+
+    let mut command3 = Command::cargo_bin("mmv").unwrap();
+    let _ = std::fs::write(&source, "recreated");
+    command3
+        .args(["apply", &plan_file])
+        .assert()
+        .failure()
+        .code(7)
+        .stderr(format!(
+            "mmv: File '{source}' has changed since the plan was made, nothing is moved\n"
+        ));
+}
+
+#[test]
+fn integration_test_diff() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let arguments = vec![
+        root.clone() + "/path/to/some_A_*",
+        root.clone() + "/path/to/changed_A_#1",
+        "--diff".to_string(),
+    ];
+    // Standard output of the test is not a terminal, so there are no colors
+    command.args(&arguments).assert().success().stdout(format!(
+        "{root}/path/to/{{some -> changed}}_A_filename.txt\nmmv: Succeded!\n"
+    ));
+}
+
+#[test]
+fn integration_test_verbosity() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let source = root.clone() + "/path/to/some_A_filename.txt";
+    let target = root.clone() + "/path/to/changed_A_filename.txt";
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    let assert = command
+        .args([
+            "-vv",
+            &(root.clone() + "/path/to/some_A_*.*"),
+            &(root.clone() + "/path/to/changed_A_#1.#2"),
+        ])
+        .assert()
+        .success()
+        .stdout(format!("{source} -> {target}\nmmv: Succeded!\n"));
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains(&format!(
+        "mmv: File '{source}' captures #1 = 'filename', #2 = 'txt'\n"
+    )));
+    assert!(stderr.contains(&format!("('{source}', '{target}'")));
+
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    command2
+        .args([
+            "-q",
+            &(root.clone() + "/path/to/changed_A_*"),
+            &(root.clone() + "/path/to/some_A_#1"),
+        ])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("");
+    assert!(std::path::Path::new(&source).exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn integration_test_non_utf8_names() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    use std::path::PathBuf;
+    let directory = setup_env();
+    let root = root_of(&directory);
+    // Latin-1 'café', as old archives keep it, is not valid UTF-8
+    let latin1_name = |name: &[u8]| {
+        let mut path = (root.clone() + "/path/to/").into_bytes();
+        path.extend_from_slice(name);
+        PathBuf::from(OsString::from_vec(path))
+    };
+    let _ = std::fs::write(latin1_name(b"caf\xe9_1.txt"), "latin-1");
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    command
+        .args([
+            &(root.clone() + "/path/to/*_1.txt"),
+            &(root.clone() + "/path/to/#1_2.txt"),
+        ])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{root}/path/to/caf\u{FFFD}_1.txt -> {root}/path/to/caf\u{FFFD}_2.txt\nmmv: Succeded!\n"
+        ));
+    assert!(!latin1_name(b"caf\xe9_1.txt").exists());
+    assert_eq!(
+        std::fs::read_to_string(latin1_name(b"caf\xe9_2.txt")).unwrap(),
+        "latin-1"
+    );
+}
+
+#[test]
+fn integration_test_translit() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let _ = std::fs::write(
+        root.clone() + "/path/to/\u{41d}\u{435}\u{431}\u{43e}.txt",
+        "sky",
+    );
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    command
+        .args([
+            root.clone() + "/path/to/\u{41d}*.txt",
+            root.clone() + "/path/to/N#{1|upper}.txt",
+        ])
+        .assert()
+        .failure()
+        .code(3)
+        .stderr("mmv: Unknown filter 'upper' in target template, expected: translit\n");
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    command2
+        .args([
+            root.clone() + "/path/to/\u{41d}*.txt",
+            root.clone() + "/path/to/N#{1|translit}.txt",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(root.clone() + "/path/to/Nebo.txt").unwrap(),
+        "sky"
+    );
+}
+
+#[test]
+fn integration_test_normalize() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    // File copied from macOS keeps 'ё' decomposed (NFD), the template has it composed (NFC)
+    let decomposed = root.clone() + "/path/to/\u{435}\u{308}lka.txt";
+    let _ = std::fs::write(&decomposed, "tree");
+    let arguments = [
+        root.clone() + "/path/to/\u{451}*.txt",
+        root.clone() + "/path/to/tree_#1.txt",
+    ];
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    command.args(&arguments).assert().failure().code(1);
+    let mut command2 = Command::cargo_bin("mmv").unwrap();
+    command2
+        .args(["--normalize", "nfc"])
+        .args(&arguments)
+        .assert()
+        .success()
+        .stdout(format!(
+            "{decomposed} -> {root}/path/to/tree_lka.txt\nmmv: Succeded!\n"
+        ));
+    assert_eq!(
+        std::fs::read_to_string(root.clone() + "/path/to/tree_lka.txt").unwrap(),
+        "tree"
+    );
+    let mut command3 = Command::cargo_bin("mmv").unwrap();
+    command3
+        .args(["--normalize", "nfkc"])
+        .args(&arguments)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn integration_test_question_mark() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    command
+        .args([
+            root.clone() + "/path/to/some_?_filename.*",
+            root.clone() + "/path/to/#1.#2",
+        ])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{root}/path/to/some_A_filename.txt -> {root}/path/to/A.txt\n\
+             {root}/path/to/some_B_filename.jpg -> {root}/path/to/B.jpg\n\
+             mmv: Succeded!\n"
+        ));
+    assert!(std::path::Path::new(&(root.clone() + "/path/to/some__filename.gif")).exists());
+}
+
+#[test]
+fn integration_test_brackets() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    for number in ["1", "2", "3"] {
+        let _ = std::fs::write(root.clone() + "/file" + number + ".txt", number);
+    }
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    command
+        .args([root.clone() + "/file[12].txt", root.clone() + "/x#1.txt"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{root}/file1.txt -> {root}/x1.txt\n\
+             {root}/file2.txt -> {root}/x2.txt\n\
+             mmv: Succeded!\n"
+        ));
+    assert_eq!(
+        std::fs::read_to_string(root.clone() + "/x2.txt").unwrap(),
+        "2"
+    );
+    assert!(std::path::Path::new(&(root.clone() + "/file3.txt")).exists());
+}
+
+#[test]
+fn integration_test_all_files_left_out() {
+    let directory = setup_env();
+    let root = root_of(&directory);
+    let _ = std::fs::create_dir(root.clone() + "/project");
+    let _ = std::fs::write(root.clone() + "/project/.env.local", "secret");
+    // The directory walk lets '*' take nothing before '.env', the plan leaves the hidden file out
+    let pattern = root.clone() + "/project/*.*";
+    let mut command = Command::cargo_bin("mmv").unwrap();
+    command
+        .args(["--no-hidden", &pattern, &(root.clone() + "/project/#1_#2")])
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(format!("mmv: Files for pattern '{}' not found\n", pattern));
+    assert!(std::path::Path::new(&(root.clone() + "/project/.env.local")).exists());
 }